@@ -0,0 +1,38 @@
+// examples/benchmark_attention.rs
+
+use cogvlm_image_preprocessor::transformer::MultiHeadAttention;
+use ndarray::Array2;
+use rand::Rng;
+use std::time::Instant;
+
+fn main() {
+    let seq_len = 577;
+    let embed_dim = 768;
+    let num_heads = 12;
+    let n_iters = 20;
+
+    let mha = MultiHeadAttention::new(embed_dim, num_heads);
+    let mut rng = rand::thread_rng();
+    let tensors: Vec<Array2<f32>> = (0..n_iters)
+        .map(|_| Array2::from_shape_fn((seq_len, embed_dim), |_| rng.gen_range(-1.0..1.0)))
+        .collect();
+
+    let t0 = Instant::now();
+    for x in &tensors {
+        let _ = mha.forward_sequential(x);
+    }
+    let sequential = t0.elapsed();
+
+    let t1 = Instant::now();
+    for x in &tensors {
+        let _ = mha.forward(x);
+    }
+    let parallel = t1.elapsed();
+
+    println!("Processed {} tensors of shape ({}, {}), {} heads:", n_iters, seq_len, embed_dim, num_heads);
+    println!("  Sequential heads : {:.2?}", sequential);
+    println!("  Parallel heads   : {:.2?}", parallel);
+    println!("Average per run:");
+    println!("  Sequential heads : {:.2?}", sequential / n_iters as u32);
+    println!("  Parallel heads   : {:.2?}", parallel / n_iters as u32);
+}
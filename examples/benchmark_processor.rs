@@ -17,6 +17,6 @@ fn main() {
     let result = process_images_in_batch(images, &processor);
     let elapsed = start.elapsed();
 
-    println!("Processed {} images in {:.2?}", result.image.len(), elapsed);
-    println!("Average per image: {:.2?}", elapsed / result.image.len() as u32);
+    println!("Processed {} images in {:.2?}", result.metadata.len(), elapsed);
+    println!("Average per image: {:.2?}", elapsed / result.metadata.len() as u32);
 }
@@ -1,4 +1,7 @@
-use cogvlm_image_preprocessor::rope::{apply_rope, apply_rope_parallel, apply_rope_simd_parallel};
+// apply_rope 系列已弃用, 保留与 RopeCache 对比
+#![allow(deprecated)]
+
+use cogvlm_image_preprocessor::rope::{apply_rope, apply_rope_parallel, apply_rope_simd_parallel, RopeCache};
 use ndarray::Array2;
 use rand::Rng;
 use std::time::Instant;
@@ -49,4 +52,16 @@ fn main() {
         println!("apply_rope_simd_parallel: processed {} tensors in {:.2?}", n_iters, elapsed);
         println!("Average per tensor: {:.2?}", elapsed / n_iters as u32);
     }
+    {
+        // 预先构建的 RopeCache, 计时不含建表
+        let cache = RopeCache::new(seq_len, dim, 10000.0);
+        let mut inputs = tensors.clone();
+        let start = Instant::now();
+        for tensor in &mut inputs {
+            cache.apply_simd(tensor);
+        }
+        let elapsed = start.elapsed();
+        println!("RopeCache::apply_simd: processed {} tensors in {:.2?}", n_iters, elapsed);
+        println!("Average per tensor: {:.2?}", elapsed / n_iters as u32);
+    }
 }
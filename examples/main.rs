@@ -1,5 +1,5 @@
 use cogvlm_image_preprocessor::processor::{ImageProcessor, process_images_in_batch};
-use ndarray::s;
+use ndarray::{s, Axis};
 use image::open;
 
 // 预处理测试
@@ -14,10 +14,10 @@ fn main() {
 
     let result = process_images_in_batch(images, &processor);
 
-    println!("Processed {} images.", result.image.len());
+    println!("Processed {} images.", result.image.len_of(Axis(0)));
     
     // 打印第 0 张图像（3通道、384×384）的前 3×3 像素值
-    let img_tensor = &result.image[0];
+    let img_tensor = result.image.index_axis(Axis(0), 0);
     
     println!("Sample of processed image tensor (C × H × W):");
     for c in 0..3 {
@@ -6,7 +6,8 @@ use ndarray::{s, Array2, Array3};
 use cogvlm_image_preprocessor::processor::ImageProcessor;
 use cogvlm_image_preprocessor::patch_embed::PatchEmbed;
 use cogvlm_image_preprocessor::patch_dropout::PatchDropout;
-use cogvlm_image_preprocessor::rope::{apply_rope};
+use cogvlm_image_preprocessor::rope::{RopeCache, RopeConfig};
+use std::sync::Arc;
 use cogvlm_image_preprocessor::transformer::TransformerLayer;
 use cogvlm_image_preprocessor::glu_projection::GLUProjection;
 
@@ -34,17 +35,14 @@ fn main() {
     println!("PatchDropout.rayon_simd输出shape: {:?}", dropped.dim());
     println!("丢弃后第2个patch元素示例: {:?}", dropped.row(1).slice(s![..8]));
 
-    // rope
-    let mut rope_in = dropped.clone();
-    apply_rope(&mut rope_in, embed_dim);
-    println!("RoPE后第2个patch元素示例: {:?}", rope_in.row(1).slice(s![..8]));
-
-    // Transformer Layer
+    // Transformer Layer, RoPE 在注意力内部按 head 作用于 Q/K
     let ff_dim = 3072;  
     let num_heads = 12; 
 
-    let block = TransformerLayer::new(embed_dim, ff_dim, num_heads);
-    let tr_out: Array2<f32> = block.forward(&rope_in);
+    let mut block = TransformerLayer::new(embed_dim, ff_dim, num_heads);
+    let rope = RopeCache::from_config(dropped.shape()[0], RopeConfig::new(embed_dim / num_heads));
+    block.mha.rope = Some(Arc::new(rope));
+    let tr_out: Array2<f32> = block.forward(&dropped);
     println!("TransformerLayer 输出 shape: {:?}", tr_out.dim());
     println!("Transformer输出第2行元素示例: {:?}", tr_out.row(1).slice(s![..8]));
 
@@ -0,0 +1,158 @@
+#!/usr/bin/env python3
+"""Regenerate the golden tensors used by `tests/parity.rs`.
+
+Every stage is produced by running the PyTorch / torchvision code itself, not a
+re-implementation:
+
+* processor      -- the CogVLM image transform, `Resize((S, S), BICUBIC)` ->
+                    `ToTensor()` -> `Normalize(mean, std)`, applied by PIL and
+                    torchvision to a non-square `IMAGE_W x IMAGE_H` image, so the
+                    bicubic resize is part of the check.
+* patch embed    -- `nn.Conv2d(3, EMBED_DIM, PATCH, stride=PATCH)`; the weight
+                    is stored flattened as `[embed_dim, 3 * patch * patch]`.
+* transformer    -- pre-norm block built from `nn.LayerNorm`,
+                    `nn.MultiheadAttention(bias=False)`, `nn.Linear` and
+                    `nn.GELU(approximate="tanh")`: `x + MHA(LN1(x))`, then
+                    `x + FFN(LN2(x))`. Projection weights are stored as
+                    `[in, out]`, i.e. the transpose of `nn.Linear.weight`.
+* glu            -- `nn.Linear(EMBED_DIM, 2 * GLU_OUT)`, then `value * relu(gate)`.
+
+The blocks are wired the way the Rust encoder is. The HF CogVLM vision tower
+(EVA2-CLIP) is not loaded directly: it uses post-norm blocks, a fused QKV with
+bias, a class token and a SwiGLU projection, so its weights do not map onto
+`VisionEncoder`.
+
+The modules run in float64; every stage is fed the float32 tensor saved as the
+previous stage's output. Requires torch, torchvision, pillow and numpy:
+
+    python3 scripts/gen_parity_fixtures.py            # writes tests/fixtures/parity
+    python3 scripts/gen_parity_fixtures.py --seed 1   # different random draw
+
+Keep the constants below in sync with `tests/parity.rs`.
+"""
+
+import argparse
+import os
+
+import numpy as np
+import torch
+import torch.nn.functional as F
+from PIL import Image
+from torch import nn
+from torchvision import transforms
+
+IMAGE_SIZE = 56
+# Non-square and smaller than IMAGE_SIZE: both axes are upsampled, so PIL's
+# antialiasing (which only kicks in when shrinking) stays out of the comparison.
+IMAGE_W = 40
+IMAGE_H = 28
+PATCH = 14
+EMBED_DIM = 32
+NUM_HEADS = 4
+FF_DIM = 64
+NUM_LAYERS = 2
+GLU_OUT = 16
+LN_EPS = 1e-5
+
+MEAN = (0.48145466, 0.4578275, 0.40821073)
+STD = (0.26862954, 0.26130258, 0.27577711)
+
+
+class Block(nn.Module):
+    def __init__(self):
+        super().__init__()
+        self.ln1 = nn.LayerNorm(EMBED_DIM, eps=LN_EPS)
+        self.attn = nn.MultiheadAttention(EMBED_DIM, NUM_HEADS, bias=False, batch_first=True)
+        self.ln2 = nn.LayerNorm(EMBED_DIM, eps=LN_EPS)
+        self.fc1 = nn.Linear(EMBED_DIM, FF_DIM)
+        self.fc2 = nn.Linear(FF_DIM, EMBED_DIM)
+        self.act = nn.GELU(approximate="tanh")
+        for ln in (self.ln1, self.ln2):
+            nn.init.normal_(ln.weight, 1.0, 0.1)
+            nn.init.normal_(ln.bias, 0.0, 0.1)
+        for fc in (self.fc1, self.fc2):
+            nn.init.normal_(fc.bias, 0.0, 0.02)
+
+    def forward(self, x):
+        h = self.ln1(x)
+        x = x + self.attn(h, h, h, need_weights=False)[0]
+        return x + self.fc2(self.act(self.fc1(self.ln2(x))))
+
+    def rust_params(self):
+        """Weights in the layout of `TransformerLayer` (`[in, out]`, 1-row vectors)."""
+        wq, wk, wv = self.attn.in_proj_weight.chunk(3)
+        return {
+            "ln1_gamma": self.ln1.weight[None],
+            "ln1_beta": self.ln1.bias[None],
+            "ln2_gamma": self.ln2.weight[None],
+            "ln2_beta": self.ln2.bias[None],
+            "wq": wq.T,
+            "wk": wk.T,
+            "wv": wv.T,
+            "wo": self.attn.out_proj.weight.T,
+            "w1": self.fc1.weight.T,
+            "b1": self.fc1.bias[None],
+            "w2": self.fc2.weight.T,
+            "b2": self.fc2.bias[None],
+        }
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.split("\n")[0])
+    parser.add_argument("--out", default=os.path.join(os.path.dirname(__file__), "..", "tests", "fixtures", "parity"))
+    parser.add_argument("--seed", type=int, default=0)
+    args = parser.parse_args()
+    os.makedirs(args.out, exist_ok=True)
+    torch.manual_seed(args.seed)
+    rng = np.random.default_rng(args.seed)
+
+    def save(name, x):
+        if isinstance(x, torch.Tensor):
+            x = x.detach().cpu().numpy()
+        np.save(os.path.join(args.out, name + ".npy"), np.ascontiguousarray(x))
+
+    def f32(x):
+        """The float32 tensor as written to disk, widened back for the next stage."""
+        return x.detach().float().double()
+
+    # processor: HWC uint8 -> normalised CHW float
+    image = rng.integers(0, 256, size=(IMAGE_H, IMAGE_W, 3), dtype=np.uint8)
+    transform = transforms.Compose([
+        transforms.Resize((IMAGE_SIZE, IMAGE_SIZE), interpolation=transforms.InterpolationMode.BICUBIC),
+        transforms.ToTensor(),
+        transforms.Normalize(MEAN, STD),
+    ])
+    pixels = transform(Image.fromarray(image, "RGB"))
+    save("image_u8", image)
+    save("processor_out", pixels.float())
+
+    with torch.no_grad():
+        # patch embed: conv with kernel == stride == PATCH
+        conv = nn.Conv2d(3, EMBED_DIM, PATCH, stride=PATCH)
+        nn.init.normal_(conv.bias, 0.0, 0.02)
+        tokens = conv.double()(f32(pixels)[None]).flatten(2)[0].T
+        save("patch_embed_weight", conv.weight.reshape(EMBED_DIM, -1).float())
+        save("patch_embed_bias", conv.bias[:, None].float())
+        save("patch_embed_out", tokens.float())
+
+        # transformer layers, each fed the float32 output of the previous one
+        x = f32(tokens)
+        for layer in range(NUM_LAYERS):
+            block = Block()
+            for name, value in block.rust_params().items():
+                save("layer%d_%s" % (layer, name), value.float())
+            out = block.double()(x[None])[0]
+            save("layer%d_out" % layer, out.float())
+            x = f32(out)
+
+        # GLU projection
+        glu = nn.Linear(EMBED_DIM, 2 * GLU_OUT)
+        nn.init.normal_(glu.bias, 0.0, 0.02)
+        save("glu_weight", glu.weight.T.float())
+        save("glu_bias", glu.bias[None].float())
+        value, gate = glu.double()(x).chunk(2, dim=-1)
+        save("glu_out", (value * F.relu(gate)).float())
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,302 @@
+// src/augment.rs
+//
+// 训练用数据增强, 作用在缩放后、归一化前取值 [0, 1] 的 (3, H, W) f32 张量上。
+// 每张图的随机数由 (seed, 图在批内的下标) 派生, 结果与 rayon 的调度顺序无关。
+// 几何变换 (裁剪、翻转、平移) 累积成 `Geometry` 返回, 用于把输出坐标映射回增强前的图像:
+//
+//   let processor = ImageProcessor::new(224).with_augmentation(Augmentation::standard(42));
+//   let batch = process_images_in_batch(images, &processor);
+
+use ndarray::{s, Array3, ArrayView3, Axis, Zip};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transform {
+    /// 随机取面积比例在 scale、宽高比在 ratio 内的区域, 双线性缩放回原尺寸
+    RandomResizedCrop { scale: (f32, f32), ratio: (f32, f32) },
+    /// 以概率 p 左右翻转
+    HorizontalFlip { p: f32 },
+    /// 亮度/对比度/饱和度各乘以 [1 - v, 1 + v] 内的随机因子, 为 0 时不变
+    ColorJitter { brightness: f32, contrast: f32, saturation: f32 },
+    /// 从 `RandOp` 中随机选 num_ops 个依次施加, magnitude 在 [0, 1]
+    RandAugment { num_ops: usize, magnitude: f32 },
+    /// 以概率 p 用随机值覆盖一个面积比例在 scale、宽高比在 ratio 内的矩形
+    RandomErasing { p: f32, scale: (f32, f32), ratio: (f32, f32) },
+}
+
+/// RandAugment-lite 的候选操作
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RandOp {
+    Identity,
+    Brightness,
+    Contrast,
+    Color,
+    Solarize,
+    Posterize,
+    AutoContrast,
+    TranslateX,
+    TranslateY,
+}
+
+const RAND_OPS: [RandOp; 9] = [
+    RandOp::Identity,
+    RandOp::Brightness,
+    RandOp::Contrast,
+    RandOp::Color,
+    RandOp::Solarize,
+    RandOp::Posterize,
+    RandOp::AutoContrast,
+    RandOp::TranslateX,
+    RandOp::TranslateY,
+];
+
+/// 几何变换的累积效果: 输出画面对应增强前 (w, h) 图像上的窗口 window = (x, y, 宽, 高),
+/// flipped 时窗口内容左右翻转。平移后窗口可以超出图像范围, 超出部分为填充
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Geometry {
+    pub window: (f32, f32, f32, f32),
+    pub flipped: bool,
+}
+
+impl Geometry {
+    pub fn identity(w: usize, h: usize) -> Self {
+        Geometry { window: (0.0, 0.0, w as f32, h as f32), flipped: false }
+    }
+
+    /// 输出上的连续坐标 (x, y) 在增强前图像上的位置, (w, h) 为输出尺寸
+    pub fn to_input(&self, x: f32, y: f32, (w, h): (usize, usize)) -> (f32, f32) {
+        let (wx, wy, ww, wh) = self.window;
+        let x = if self.flipped { w as f32 - x } else { x };
+        (wx + x * ww / w as f32, wy + y * wh / h as f32)
+    }
+
+    // 当前画面 (w, h) 中 (left, top, cw, ch) 的区域被缩放为新的画面
+    fn crop(&mut self, (left, top, cw, ch): (f32, f32, f32, f32), (w, h): (usize, usize)) {
+        let (wx, wy, ww, wh) = self.window;
+        let (sx, sy) = (ww / w as f32, wh / h as f32);
+        // 翻转时当前画面的 left 对应窗口右侧
+        let left = if self.flipped { w as f32 - left - cw } else { left };
+        self.window = (wx + left * sx, wy + top * sy, cw * sx, ch * sy);
+    }
+}
+
+/// 按顺序施加的一组变换
+#[derive(Clone, Debug, PartialEq)]
+pub struct Augmentation {
+    pub seed: u64,
+    pub transforms: Vec<Transform>,
+}
+
+impl Augmentation {
+    pub fn new(seed: u64) -> Self {
+        Augmentation { seed, transforms: Vec::new() }
+    }
+
+    pub fn then(mut self, transform: Transform) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    /// 常用组合: 随机裁剪缩放 + 翻转 + 颜色抖动 + 随机擦除 (参数同 torchvision / timm 的默认值)
+    pub fn standard(seed: u64) -> Self {
+        Augmentation::new(seed)
+            .then(Transform::RandomResizedCrop { scale: (0.08, 1.0), ratio: (3.0 / 4.0, 4.0 / 3.0) })
+            .then(Transform::HorizontalFlip { p: 0.5 })
+            .then(Transform::ColorJitter { brightness: 0.4, contrast: 0.4, saturation: 0.4 })
+            .then(Transform::RandomErasing { p: 0.25, scale: (0.02, 1.0 / 3.0), ratio: (0.3, 3.3) })
+    }
+
+    /// 第 index 张图的随机数生成器
+    pub fn rng(&self, index: u64) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+
+    /// 返回其中几何变换的累积效果
+    pub fn apply(&self, x: &mut Array3<f32>, index: u64) -> Geometry {
+        let mut rng = self.rng(index);
+        let mut geometry = Geometry::identity(x.shape()[2], x.shape()[1]);
+        for t in &self.transforms {
+            t.apply_tracked(x, &mut geometry, &mut rng);
+        }
+        geometry
+    }
+}
+
+impl Transform {
+    pub fn apply<R: Rng + ?Sized>(&self, x: &mut Array3<f32>, rng: &mut R) {
+        let mut geometry = Geometry::identity(x.shape()[2], x.shape()[1]);
+        self.apply_tracked(x, &mut geometry, rng);
+    }
+
+    fn apply_tracked<R: Rng + ?Sized>(&self, x: &mut Array3<f32>, geometry: &mut Geometry, rng: &mut R) {
+        let (_, h, w) = x.dim();
+        match *self {
+            Transform::RandomResizedCrop { scale, ratio } => {
+                let (top, left, ch, cw) = sample_box(h, w, scale, ratio, rng).unwrap_or((0, 0, h, w));
+                *x = resize_bilinear(x.slice(s![.., top..top + ch, left..left + cw]), h, w);
+                geometry.crop((left as f32, top as f32, cw as f32, ch as f32), (w, h));
+            }
+            Transform::HorizontalFlip { p } => {
+                if rng.gen::<f32>() < p {
+                    *x = x.slice(s![.., .., ..;-1]).to_owned();
+                    geometry.flipped = !geometry.flipped;
+                }
+            }
+            Transform::ColorJitter { brightness, contrast, saturation } => {
+                if brightness > 0.0 {
+                    adjust_brightness(x, rng.gen_range(1.0 - brightness..=1.0 + brightness));
+                }
+                if contrast > 0.0 {
+                    adjust_contrast(x, rng.gen_range(1.0 - contrast..=1.0 + contrast));
+                }
+                if saturation > 0.0 {
+                    adjust_saturation(x, rng.gen_range(1.0 - saturation..=1.0 + saturation));
+                }
+            }
+            Transform::RandAugment { num_ops, magnitude } => {
+                for _ in 0..num_ops {
+                    let op = RAND_OPS[rng.gen_range(0..RAND_OPS.len())];
+                    op.apply_tracked(x, magnitude, geometry, rng);
+                }
+            }
+            Transform::RandomErasing { p, scale, ratio } => {
+                if rng.gen::<f32>() >= p {
+                    return;
+                }
+                if let Some((top, left, eh, ew)) = sample_box(h, w, scale, ratio, rng) {
+                    x.slice_mut(s![.., top..top + eh, left..left + ew]).mapv_inplace(|_| rng.gen());
+                }
+            }
+        }
+    }
+}
+
+impl RandOp {
+    pub fn apply<R: Rng + ?Sized>(self, x: &mut Array3<f32>, magnitude: f32, rng: &mut R) {
+        let mut geometry = Geometry::identity(x.shape()[2], x.shape()[1]);
+        self.apply_tracked(x, magnitude, &mut geometry, rng);
+    }
+
+    fn apply_tracked<R: Rng + ?Sized>(self, x: &mut Array3<f32>, magnitude: f32, geometry: &mut Geometry, rng: &mut R) {
+        let (_, h, w) = x.dim();
+        // 增强类操作随机取方向
+        let signed = if rng.gen::<bool>() { magnitude } else { -magnitude };
+        match self {
+            RandOp::Identity => {}
+            RandOp::Brightness => adjust_brightness(x, 1.0 + 0.9 * signed),
+            RandOp::Contrast => adjust_contrast(x, 1.0 + 0.9 * signed),
+            RandOp::Color => adjust_saturation(x, 1.0 + 0.9 * signed),
+            RandOp::Solarize => {
+                let threshold = 1.0 - magnitude;
+                x.mapv_inplace(|v| if v >= threshold { 1.0 - v } else { v });
+            }
+            RandOp::Posterize => {
+                let levels = 2f32.powi(8 - (4.0 * magnitude).round() as i32);
+                x.mapv_inplace(|v| (v * (levels - 1.0)).round() / (levels - 1.0));
+            }
+            RandOp::AutoContrast => {
+                for mut channel in x.outer_iter_mut() {
+                    let (lo, hi) = channel.iter().fold((f32::MAX, f32::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+                    if hi > lo {
+                        channel.mapv_inplace(|v| (v - lo) / (hi - lo));
+                    }
+                }
+            }
+            RandOp::TranslateX => {
+                let dx = (0.3 * signed * w as f32).round() as isize;
+                translate(x, 0, dx);
+                geometry.crop((-dx as f32, 0.0, w as f32, h as f32), (w, h));
+            }
+            RandOp::TranslateY => {
+                let dy = (0.3 * signed * h as f32).round() as isize;
+                translate(x, dy, 0);
+                geometry.crop((0.0, -dy as f32, w as f32, h as f32), (w, h));
+            }
+        }
+    }
+}
+
+// torchvision 的 RandomResizedCrop 取框方式: 最多尝试 10 次, 返回 (top, left, h, w)
+fn sample_box<R: Rng + ?Sized>(
+    h: usize,
+    w: usize,
+    scale: (f32, f32),
+    ratio: (f32, f32),
+    rng: &mut R,
+) -> Option<(usize, usize, usize, usize)> {
+    let area = (h * w) as f32;
+    let (log_lo, log_hi) = (ratio.0.ln(), ratio.1.ln());
+    for _ in 0..10 {
+        let target = area * rng.gen_range(scale.0..=scale.1);
+        let aspect = rng.gen_range(log_lo..=log_hi).exp();
+        let bw = (target * aspect).sqrt().round() as usize;
+        let bh = (target / aspect).sqrt().round() as usize;
+        if bw > 0 && bh > 0 && bw <= w && bh <= h {
+            return Some((rng.gen_range(0..=h - bh), rng.gen_range(0..=w - bw), bh, bw));
+        }
+    }
+    None
+}
+
+// 半像素对齐的双线性缩放, 边缘取最近像素
+fn resize_bilinear(x: ArrayView3<f32>, out_h: usize, out_w: usize) -> Array3<f32> {
+    let (c, in_h, in_w) = x.dim();
+    let coords = |out: usize, len_in: usize, len_out: usize| {
+        let f = ((out as f32 + 0.5) * len_in as f32 / len_out as f32 - 0.5).clamp(0.0, (len_in - 1) as f32);
+        let i = f.floor() as usize;
+        (i, (i + 1).min(len_in - 1), f - i as f32)
+    };
+    let mut out = Array3::<f32>::zeros((c, out_h, out_w));
+    for y in 0..out_h {
+        let (y0, y1, fy) = coords(y, in_h, out_h);
+        for xo in 0..out_w {
+            let (x0, x1, fx) = coords(xo, in_w, out_w);
+            for ch in 0..c {
+                let top = x[[ch, y0, x0]] * (1.0 - fx) + x[[ch, y0, x1]] * fx;
+                let bottom = x[[ch, y1, x0]] * (1.0 - fx) + x[[ch, y1, x1]] * fx;
+                out[[ch, y, xo]] = top * (1.0 - fy) + bottom * fy;
+            }
+        }
+    }
+    out
+}
+
+// ITU-R 601 亮度, (H, W)
+fn grayscale(x: &Array3<f32>) -> ndarray::Array2<f32> {
+    let (r, g, b) = (x.index_axis(Axis(0), 0), x.index_axis(Axis(0), 1), x.index_axis(Axis(0), 2));
+    Zip::from(&r).and(&g).and(&b).map_collect(|&r, &g, &b| 0.299 * r + 0.587 * g + 0.114 * b)
+}
+
+fn adjust_brightness(x: &mut Array3<f32>, factor: f32) {
+    x.mapv_inplace(|v| (v * factor).clamp(0.0, 1.0));
+}
+
+fn adjust_contrast(x: &mut Array3<f32>, factor: f32) {
+    let mean = grayscale(x).mean().unwrap_or(0.0);
+    x.mapv_inplace(|v| ((v - mean) * factor + mean).clamp(0.0, 1.0));
+}
+
+fn adjust_saturation(x: &mut Array3<f32>, factor: f32) {
+    let gray = grayscale(x);
+    for mut channel in x.outer_iter_mut() {
+        Zip::from(&mut channel).and(&gray).for_each(|v, &g| *v = ((*v - g) * factor + g).clamp(0.0, 1.0));
+    }
+}
+
+// 平移 (dy, dx) 像素, 移出的区域填 0.5; 输出 (y, x) 处为输入 (y - dy, x - dx)
+fn translate(x: &mut Array3<f32>, dy: isize, dx: isize) {
+    let (_, h, w) = x.dim();
+    let mut out = Array3::from_elem(x.raw_dim(), 0.5);
+    let shift = |d: isize, len: usize| {
+        let d = d.clamp(-(len as isize), len as isize);
+        if d >= 0 {
+            (0..len - d as usize, d as usize..len)
+        } else {
+            (-d as usize..len, 0..len - (-d) as usize)
+        }
+    };
+    let ((src_y, dst_y), (src_x, dst_x)) = (shift(dy, h), shift(dx, w));
+    out.slice_mut(s![.., dst_y, dst_x]).assign(&x.slice(s![.., src_y, src_x]));
+    *x = out;
+}
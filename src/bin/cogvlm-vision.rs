@@ -0,0 +1,372 @@
+// src/bin/cogvlm-vision.rs
+//
+// 命令行入口: 预处理 / 编码 / 查看权重 / 基准测试
+
+use clap::{Args, Parser, Subcommand};
+use cogvlm_image_preprocessor::encoder::{EncoderConfig, LoadError, VisionEncoder};
+use cogvlm_image_preprocessor::processor::ImageProcessor;
+use cogvlm_image_preprocessor::stream::StreamConfig;
+use ndarray::{Array2, Array3, Array4, Axis};
+use ndarray_npy::write_npy;
+use safetensors::{Dtype, SafeTensors};
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const IMAGE_EXTENSIONS: [&str; 7] = ["jpg", "jpeg", "png", "bmp", "webp", "gif", "tiff"];
+
+#[derive(Parser)]
+#[command(name = "cogvlm-vision", about = "CogVLM vision encoder tools")]
+struct Cli {
+    /// rayon 线程数, 默认使用全部核心
+    #[arg(long, global = true)]
+    threads: Option<usize>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 预处理图片并把归一化后的 (3, H, W) 张量保存为 <文件名>.npy (如 a.jpg.npy)
+    Preprocess {
+        /// 图片文件或目录
+        input: PathBuf,
+        /// 输出目录
+        #[arg(short, long)]
+        output: PathBuf,
+        #[arg(long, default_value_t = 224)]
+        image_size: u32,
+    },
+    /// 编码图片, 按输出扩展名保存为 .npy / .safetensors / .jsonl
+    Encode {
+        /// 图片文件或目录
+        input: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+        #[command(flatten)]
+        model: ModelArgs,
+    },
+    /// 列出 safetensors 权重文件中的张量
+    InspectWeights {
+        weights: PathBuf,
+    },
+    /// 预处理和编码的耗时统计
+    Bench {
+        /// 测试图片, 缺省时使用全零输入
+        #[arg(long)]
+        image: Option<PathBuf>,
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+        iters: u32,
+        #[command(flatten)]
+        model: ModelArgs,
+    },
+    /// 启动本地 HTTP 编码服务 (需要 server feature)
+    #[cfg(feature = "server")]
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+        #[arg(long, default_value_t = 8)]
+        max_batch_size: usize,
+        /// 凑批等待时间 (毫秒)
+        #[arg(long, default_value_t = 10)]
+        batch_timeout_ms: u64,
+        #[arg(long, default_value_t = 4)]
+        workers: usize,
+        /// 内存映射 --weights 而不复制, 多个服务进程共享 page cache
+        #[arg(long)]
+        mmap: bool,
+        #[command(flatten)]
+        model: ModelArgs,
+    },
+}
+
+#[derive(Args)]
+struct ModelArgs {
+    /// JSON 格式的 EncoderConfig, 缺省时取权重文件头中的配置; 两者都有时必须一致
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// safetensors 权重, 缺省时随机初始化
+    #[arg(long)]
+    weights: Option<PathBuf>,
+    /// 覆盖配置中的 image_size
+    #[arg(long)]
+    image_size: Option<u32>,
+}
+
+impl ModelArgs {
+    fn config(&self) -> Result<EncoderConfig, Box<dyn Error>> {
+        let stored = match &self.weights {
+            Some(path) => stored_config(EncoderConfig::from_safetensors_header(path))?,
+            None => None,
+        };
+        self.config_with(stored)
+    }
+
+    // stored 为权重文件头中的配置, 层数、downsample、rope_2d 等结构以它为准
+    fn config_with(&self, stored: Option<EncoderConfig>) -> Result<EncoderConfig, Box<dyn Error>> {
+        let mut config = match (&self.config, stored) {
+            (Some(path), stored) => {
+                let config = EncoderConfig::from_json_file(path)?;
+                if stored.is_some_and(|stored| stored != config) {
+                    return Err(format!("{} does not match the config stored in the weights", path.display()).into());
+                }
+                config
+            }
+            (None, Some(stored)) => stored,
+            (None, None) if self.weights.is_some() => {
+                return Err("the weights file has no stored config, pass --config".into());
+            }
+            (None, None) => EncoderConfig::default(),
+        };
+        if let Some(size) = self.image_size {
+            config.image_size = size;
+        }
+        Ok(config)
+    }
+
+    fn build(&self) -> Result<VisionEncoder, Box<dyn Error>> {
+        let config = self.config()?;
+        match &self.weights {
+            Some(path) => Ok(VisionEncoder::load_safetensors(config, path)?),
+            None => {
+                eprintln!("warning: no --weights given, using randomly initialized encoder");
+                Ok(VisionEncoder::new(config))
+            }
+        }
+    }
+}
+
+// 没有写入配置的权重文件 (非 save_safetensors 生成) 不算错误
+fn stored_config(config: Result<EncoderConfig, LoadError>) -> Result<Option<EncoderConfig>, LoadError> {
+    match config {
+        Ok(config) => Ok(Some(config)),
+        Err(LoadError::MissingConfig) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    if let Some(threads) = cli.threads {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()?;
+    }
+
+    match cli.command {
+        Command::Preprocess { input, output, image_size } => preprocess(&input, &output, image_size),
+        Command::Encode { input, output, model } => encode(&input, &output, &model),
+        Command::InspectWeights { weights } => inspect_weights(&weights),
+        Command::Bench { image, iters, model } => bench(image.as_deref(), iters, &model),
+        #[cfg(feature = "server")]
+        Command::Serve { addr, max_batch_size, batch_timeout_ms, workers, mmap, model } => {
+            use cogvlm_image_preprocessor::mmap::MappedWeights;
+            use cogvlm_image_preprocessor::server::{EmbeddingServer, ServerConfig};
+            let config = ServerConfig {
+                addr,
+                max_batch_size,
+                batch_timeout: std::time::Duration::from_millis(batch_timeout_ms),
+                workers,
+                ..ServerConfig::default()
+            };
+            let server = if mmap {
+                let path = model.weights.as_ref().ok_or("--mmap requires --weights")?;
+                // 服务运行到进程退出, 映射无需释放
+                let weights: &'static MappedWeights = Box::leak(Box::new(MappedWeights::open(path)?));
+                let encoder_config = model.config_with(stored_config(weights.config())?)?;
+                EmbeddingServer::bind(weights.encoder_with_config(encoder_config)?, config)
+            } else {
+                EmbeddingServer::bind(model.build()?, config)
+            };
+            let server = server.map_err(|e| e.to_string())?;
+            eprintln!("listening on http://{}", server.local_addr().map_or("?".into(), |a| a.to_string()));
+            server.run();
+            Ok(())
+        }
+    }
+}
+
+fn collect_images(input: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if input.is_file() {
+        return Ok(vec![input.to_path_buf()]);
+    }
+
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(input)? {
+        let path = entry?.path();
+        let is_image = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()));
+        if path.is_file() && is_image {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    if paths.is_empty() {
+        return Err(format!("no images found in {}", input.display()).into());
+    }
+    Ok(paths)
+}
+
+// 逐批读取并预处理, 读不了的文件只报告不中断; 每批成功的图调用一次 f(在 paths 中的下标, (n, 3, S, S)),
+// 有失败时在全部处理完后返回错误
+fn for_each_batch<F>(paths: &[PathBuf], processor: &ImageProcessor, mut f: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut(&[usize], &Array4<f32>) -> Result<(), Box<dyn Error>>,
+{
+    let mut failed = 0;
+    for batch in processor.stream(paths.to_vec(), &StreamConfig::default())? {
+        f(&batch.indices, &batch.output.image)?;
+        for (i, err) in &batch.errors {
+            eprintln!("{}: {}", paths[*i].display(), err);
+        }
+        failed += batch.errors.len();
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} images could not be read", failed, paths.len()).into());
+    }
+    Ok(())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+fn preprocess(input: &Path, output: &Path, image_size: u32) -> Result<(), Box<dyn Error>> {
+    let paths = collect_images(input)?;
+    let processor = ImageProcessor::new(image_size);
+    std::fs::create_dir_all(output)?;
+
+    // 用完整文件名, a.jpg 和 a.png 不会写到同一个文件
+    for_each_batch(&paths, &processor, |indices, images| {
+        for (&i, tensor) in indices.iter().zip(images.outer_iter()) {
+            let path = &paths[i];
+            let out_path = output.join(format!("{}.npy", file_name(path)));
+            write_npy(&out_path, &tensor)?;
+            println!("{} -> {} {:?}", path.display(), out_path.display(), tensor.dim());
+        }
+        Ok(())
+    })
+}
+
+fn encode(input: &Path, output: &Path, model: &ModelArgs) -> Result<(), Box<dyn Error>> {
+    let encoder = model.build()?;
+    let paths = collect_images(input)?;
+    let processor = ImageProcessor::for_encoder(&encoder.config);
+    let format = output.extension().and_then(|e| e.to_str());
+    if !matches!(format, Some("npy" | "safetensors" | "jsonl")) {
+        return Err("output must end with .npy, .safetensors or .jsonl".into());
+    }
+
+    // 与 preprocess 相同, 读不了的文件报告后跳过, 其余照常编码写出
+    let (mut encoded, mut embeddings) = (Vec::new(), Vec::new());
+    let start = Instant::now();
+    let read = for_each_batch(&paths, &processor, |indices, images| {
+        let tensors: Vec<_> = images.outer_iter().map(|x| x.to_owned()).collect();
+        embeddings.extend(encoder.forward_batch(&tensors));
+        encoded.extend(indices.iter().map(|&i| paths[i].clone()));
+        Ok(())
+    });
+    eprintln!("encoded {} images in {:.2?}", embeddings.len(), start.elapsed());
+
+    if !embeddings.is_empty() {
+        match format {
+            Some("npy") => {
+                let views: Vec<_> = embeddings.iter().map(|e| e.view()).collect();
+                let stacked = ndarray::stack(Axis(0), &views)?;
+                write_npy(output, &stacked)?;
+            }
+            Some("safetensors") => write_safetensors(output, &encoded, &embeddings)?,
+            _ => write_jsonl(output, &encoded, &embeddings)?,
+        }
+        println!("wrote {}", output.display());
+    }
+    read
+}
+
+fn write_safetensors(output: &Path, paths: &[PathBuf], embeddings: &[Array2<f32>]) -> Result<(), Box<dyn Error>> {
+    let buffers: Vec<Vec<u8>> = embeddings
+        .iter()
+        .map(|e| e.iter().flat_map(|v| v.to_le_bytes()).collect())
+        .collect();
+    let views = paths
+        .iter()
+        .zip(embeddings)
+        .zip(&buffers)
+        .map(|((path, e), buf)| {
+            let view = safetensors::tensor::TensorView::new(Dtype::F32, e.shape().to_vec(), buf)?;
+            Ok((file_name(path), view))
+        })
+        .collect::<Result<Vec<_>, safetensors::SafeTensorError>>()?;
+    safetensors::serialize_to_file(views, &None, output)?;
+    Ok(())
+}
+
+fn write_jsonl(output: &Path, paths: &[PathBuf], embeddings: &[Array2<f32>]) -> Result<(), Box<dyn Error>> {
+    let mut out = std::io::BufWriter::new(std::fs::File::create(output)?);
+    for (path, e) in paths.iter().zip(embeddings) {
+        let rows: Vec<Vec<f32>> = e.outer_iter().map(|r| r.to_vec()).collect();
+        let line = serde_json::json!({
+            "path": path.to_string_lossy(),
+            "shape": e.shape(),
+            "embedding": rows,
+        });
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn inspect_weights(weights: &Path) -> Result<(), Box<dyn Error>> {
+    let bytes = std::fs::read(weights)?;
+    let tensors = SafeTensors::deserialize(&bytes)?;
+    let (_, metadata) = SafeTensors::read_metadata(&bytes)?;
+
+    if let Some(meta) = metadata.metadata() {
+        let mut keys: Vec<_> = meta.keys().collect();
+        keys.sort();
+        for key in keys {
+            println!("# {}: {}", key, meta[key]);
+        }
+    }
+
+    let mut entries = tensors.tensors();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut total = 0usize;
+    for (name, view) in &entries {
+        let numel: usize = view.shape().iter().product();
+        total += numel;
+        println!("{:<40} {:?} {:?}", name, view.dtype(), view.shape());
+    }
+    println!("{} tensors, {} parameters", entries.len(), total);
+    Ok(())
+}
+
+fn bench(image: Option<&Path>, iters: u32, model: &ModelArgs) -> Result<(), Box<dyn Error>> {
+    let encoder = model.build()?;
+    let size = encoder.config.image_size as usize;
+    let processor = ImageProcessor::for_encoder(&encoder.config);
+
+    let tensor = match image {
+        Some(path) => {
+            let img = image::open(path)?;
+            let start = Instant::now();
+            for _ in 0..iters {
+                let _ = processor.preprocess(&img);
+            }
+            let elapsed = start.elapsed();
+            println!("preprocess: {:.2?} total, {:.2?} per image", elapsed, elapsed / iters);
+            processor.preprocess(&img)
+        }
+        None => Array3::zeros((3, size, size)),
+    };
+
+    let start = Instant::now();
+    for _ in 0..iters {
+        let _ = encoder.forward(&tensor);
+    }
+    let elapsed = start.elapsed();
+    println!("encode    : {:.2?} total, {:.2?} per image", elapsed, elapsed / iters);
+    Ok(())
+}
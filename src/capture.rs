@@ -0,0 +1,71 @@
+use ndarray::{ArrayBase, ArrayD, Data, Dimension};
+use ndarray_npy::{write_npy, WriteNpyError};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// 中间激活收集器, 用于和 Python 参考实现逐层对比。
+///
+/// 各模块的 `forward_captured` 以 `name` 为前缀记录激活, 例如
+/// `layer0.ln1`、`layer0.attn`、`layer0`。`filter` 为空时全部记录;
+/// 否则只记录名字等于某个前缀或以 `前缀.` 开头的激活。
+pub struct ActivationCapture {
+    pub filter: Vec<String>,
+    pub activations: BTreeMap<String, ArrayD<f32>>,
+}
+
+impl ActivationCapture {
+    pub fn new() -> Self {
+        ActivationCapture {
+            filter: Vec::new(),
+            activations: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_filter<I, S>(filter: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        ActivationCapture {
+            filter: filter.into_iter().map(Into::into).collect(),
+            activations: BTreeMap::new(),
+        }
+    }
+
+    pub fn wants(&self, name: &str) -> bool {
+        self.filter.is_empty()
+            || self.filter.iter().any(|p| {
+                name == p || (name.starts_with(p.as_str()) && name[p.len()..].starts_with('.'))
+            })
+    }
+
+    pub fn record<S, D>(&mut self, name: &str, value: &ArrayBase<S, D>)
+    where
+        S: Data<Elem = f32>,
+        D: Dimension,
+    {
+        if self.wants(name) {
+            self.activations.insert(name.to_string(), value.to_owned().into_dyn());
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ArrayD<f32>> {
+        self.activations.get(name)
+    }
+
+    /// 把记录的激活写成 `<dir>/<name>.npy`
+    pub fn write_npy_dir<P: AsRef<Path>>(&self, dir: P) -> Result<(), WriteNpyError> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        for (name, value) in &self.activations {
+            write_npy(dir.join(format!("{}.npy", name)), value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for ActivationCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
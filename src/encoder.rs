@@ -0,0 +1,642 @@
+use ndarray::{Array2, Array3, ArrayBase, ArrayD, Data, Ix2, OwnedRepr};
+use ndarray_npy::{read_npy, write_npy, ReadNpyError, WriteNpyError};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use safetensors::tensor::TensorView;
+use safetensors::{Dtype, SafeTensorError, SafeTensors};
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use crate::capture::ActivationCapture;
+use crate::glu_projection::GLUProjection;
+use crate::packing;
+use crate::patch_embed::PatchEmbed;
+use crate::resampler::{ConvDownsampler, DownsampleKind, Downsampler, TokenResampler};
+use crate::rope::{RopeCache, RopeConfig};
+use crate::tiling::{TileEmbedding, TiledEmbeddings, TiledImage};
+use std::sync::Arc;
+use crate::video::{apply_temporal, Temporal};
+use crate::transformer::{FeedForward, LayerNorm, MultiHeadAttention, TransformerLayer};
+
+/// 编码器结构参数, 可从 JSON 读取, 缺省字段取默认值
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncoderConfig {
+    pub image_size: u32,
+    pub patch_size: usize,
+    pub embed_dim: usize,
+    pub num_heads: usize,
+    /// K/V head 数 (GQA/MQA), 缺省等于 num_heads
+    pub num_kv_heads: Option<usize>,
+    pub ff_dim: usize,
+    pub num_layers: usize,
+    pub out_dim: usize,
+    /// 编码器与 GLUProjection 之间的 token 压缩方式
+    pub downsample: DownsampleKind,
+    /// downsample = "resampler" 时输出的 token 数
+    pub num_queries: usize,
+    /// 随机初始化的种子, 缺省时每次运行不同
+    pub seed: Option<u64>,
+    /// 按 patch 网格的行/列施加 2D RoPE, 缺省为按展开顺序的 1D RoPE
+    pub rope_2d: bool,
+}
+
+impl Default for EncoderConfig {
+    fn default() -> Self {
+        EncoderConfig {
+            image_size: 224,
+            patch_size: 16,
+            embed_dim: 768,
+            num_heads: 12,
+            num_kv_heads: None,
+            ff_dim: 3072,
+            num_layers: 12,
+            out_dim: 512,
+            downsample: DownsampleKind::None,
+            num_queries: 64,
+            seed: None,
+            rope_2d: false,
+        }
+    }
+}
+
+impl EncoderConfig {
+    pub fn from_json_file<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// `save_safetensors` 写入文件头的配置; 只读文件头, 不读取张量数据
+    pub fn from_safetensors_header<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let mut file = std::fs::File::open(path)?;
+        let mut len = [0u8; 8];
+        file.read_exact(&mut len)?;
+        let len = u64::from_le_bytes(len);
+        if len > MAX_HEADER_BYTES {
+            return Err(SafeTensorError::HeaderTooLarge.into());
+        }
+        let mut header = Vec::with_capacity(len as usize);
+        file.take(len).read_to_end(&mut header)?;
+        if header.len() as u64 != len {
+            return Err(SafeTensorError::InvalidHeaderLength.into());
+        }
+
+        #[derive(Deserialize)]
+        struct Header {
+            #[serde(rename = "__metadata__", default)]
+            metadata: HashMap<String, String>,
+        }
+        let header: Header = serde_json::from_slice(&header)?;
+        let config = header.metadata.get(CONFIG_KEY).ok_or(LoadError::MissingConfig)?;
+        Ok(serde_json::from_str(config)?)
+    }
+
+    pub fn kv_heads(&self) -> usize {
+        self.num_kv_heads.unwrap_or(self.num_heads)
+    }
+
+    pub fn num_patches(&self) -> usize {
+        let (h, w) = self.grid();
+        h * w
+    }
+
+    /// patch 网格 (行, 列)
+    pub fn grid(&self) -> (usize, usize) {
+        let grid = self.image_size as usize / self.patch_size;
+        (grid, grid)
+    }
+
+    /// rows x cols 网格的 RoPE 表, head_dim 取 embed_dim / num_heads
+    pub fn rope_cache(&self, grid: (usize, usize)) -> RopeCache {
+        self.rope_cache_with(grid, RopeConfig::new(self.embed_dim / self.num_heads))
+    }
+
+    fn rope_cache_with(&self, grid: (usize, usize), rope: RopeConfig) -> RopeCache {
+        if self.rope_2d {
+            RopeCache::from_grid(grid, rope)
+        } else {
+            RopeCache::from_config(grid.0 * grid.1, rope)
+        }
+    }
+}
+
+// save_safetensors 写入的 metadata
+const CONFIG_KEY: &str = "config";
+const FORMAT: &str = "cogvlm-vision";
+// 与 safetensors 的文件头上限一致
+const MAX_HEADER_BYTES: u64 = 100_000_000;
+
+/// 权重读写错误
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    SafeTensors(SafeTensorError),
+    Dtype { name: String, dtype: Dtype },
+    Shape { name: String, expected: Vec<usize>, actual: Vec<usize> },
+    ReadNpy(ReadNpyError),
+    WriteNpy(WriteNpyError),
+    /// safetensors 文件头中没有 `config` metadata
+    MissingConfig,
+    /// 内存映射时张量数据不是 4 字节对齐, 只能用 `load_safetensors` 复制加载
+    Unaligned { name: String },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "io error: {}", e),
+            LoadError::Json(e) => write!(f, "invalid config: {}", e),
+            LoadError::SafeTensors(e) => write!(f, "safetensors error: {}", e),
+            LoadError::Dtype { name, dtype } => {
+                write!(f, "tensor `{}` has dtype {:?}, expected F32", name, dtype)
+            }
+            LoadError::Shape { name, expected, actual } => {
+                write!(f, "tensor `{}` has shape {:?}, expected {:?}", name, actual, expected)
+            }
+            LoadError::ReadNpy(e) => write!(f, "failed to read npy: {}", e),
+            LoadError::WriteNpy(e) => write!(f, "failed to write npy: {}", e),
+            LoadError::MissingConfig => write!(f, "no `{}` entry in safetensors metadata", CONFIG_KEY),
+            LoadError::Unaligned { name } => write!(f, "tensor `{}` is not 4-byte aligned, cannot be mapped", name),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for LoadError {
+    fn from(e: serde_json::Error) -> Self {
+        LoadError::Json(e)
+    }
+}
+
+impl From<ReadNpyError> for LoadError {
+    fn from(e: ReadNpyError) -> Self {
+        LoadError::ReadNpy(e)
+    }
+}
+
+impl From<WriteNpyError> for LoadError {
+    fn from(e: WriteNpyError) -> Self {
+        LoadError::WriteNpy(e)
+    }
+}
+
+impl From<SafeTensorError> for LoadError {
+    fn from(e: SafeTensorError) -> Self {
+        LoadError::SafeTensors(e)
+    }
+}
+
+// 完整的图像编码器: PatchEmbed -> TransformerLayer x N -> Downsampler -> GLUProjection
+// RoPE 由各层的 MultiHeadAttention 在 Q/K 上按 head 施加, 各层共享同一个 RopeCache
+// 权重可以是自有的 Array2, 也可以借用内存映射的文件 (见 `mmap::MappedWeights`)
+pub struct VisionEncoder<S: Data<Elem = f32> = OwnedRepr<f32>> {
+    pub config: EncoderConfig,
+    pub patch_embed: PatchEmbed<S>,
+    pub layers: Vec<TransformerLayer<S>>,
+    pub downsampler: Downsampler<S>,
+    pub glu: GLUProjection<S>,
+}
+
+impl VisionEncoder {
+    /// 随机初始化; config.seed 设置时结果可复现
+    pub fn new(config: EncoderConfig) -> Self {
+        match config.seed {
+            Some(seed) => Self::new_with_rng(config, &mut StdRng::seed_from_u64(seed)),
+            None => Self::new_with_rng(config, &mut rand::thread_rng()),
+        }
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(config: EncoderConfig, rng: &mut R) -> Self {
+        let patch_embed = PatchEmbed::new_with_rng(config.patch_size, config.embed_dim, rng);
+        let rope = Arc::new(config.rope_cache(config.grid()));
+        let layers = (0..config.num_layers)
+            .map(|_| {
+                let mut layer = TransformerLayer::new_with_rng(
+                    config.embed_dim,
+                    config.ff_dim,
+                    config.num_heads,
+                    config.kv_heads(),
+                    rng,
+                );
+                layer.mha.rope = Some(Arc::clone(&rope));
+                layer
+            })
+            .collect();
+        let downsampler = Downsampler::new(
+            config.downsample,
+            config.embed_dim,
+            config.num_queries,
+            config.num_heads,
+            config.ff_dim,
+            rng,
+        );
+        let glu = GLUProjection::new_with_rng(config.embed_dim, config.out_dim, rng);
+        VisionEncoder { config, patch_embed, layers, downsampler, glu }
+    }
+
+    /// 从 safetensors 文件加载 F32 权重, 张量命名见 `named_weights`;
+    /// 一维张量在元素数一致且目标有一维为 1 时按目标形状展开, 缺少 `patch_embed.bias` 或 `glu.bias` 时不使用偏置
+    pub fn load_safetensors<P: AsRef<Path>>(config: EncoderConfig, path: P) -> Result<Self, LoadError> {
+        let bytes = std::fs::read(path)?;
+        let tensors = SafeTensors::deserialize(&bytes)?;
+        let names = tensors.names();
+        Self::from_source(config, |name| names.iter().any(|n| n.as_str() == name), |name, shape| {
+            take(&tensors, name, shape)
+        })
+    }
+
+    /// 读取 `save_safetensors` 写出的文件, 配置取自文件头的 metadata
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let bytes = std::fs::read(&path)?;
+        let config = config_from_header(&bytes)?;
+        Self::load_safetensors(config, path)
+    }
+
+    pub fn load_npy_dir<P: AsRef<Path>>(dir: P) -> Result<Self, LoadError> {
+        let dir = dir.as_ref();
+        let config = EncoderConfig::from_json_file(dir.join("config.json"))?;
+        let file = |name: &str| dir.join(format!("{}.npy", name));
+        Self::from_source(config, |name| file(name).is_file(), |name, shape| {
+            let array: ArrayD<f32> = read_npy(file(name))?;
+            check_shape(name, array.shape().to_vec(), shape)?;
+            Ok(Array2::from_shape_vec(shape, array.iter().copied().collect()).unwrap())
+        })
+    }
+}
+
+impl<S: Data<Elem = f32> + Sync> VisionEncoder<S> {
+    // 按 config 中的形状逐个向 take(name, 形状) 取权重, 自有与借用的存储共用
+    pub(crate) fn from_source(
+        config: EncoderConfig,
+        has: impl Fn(&str) -> bool,
+        mut take: impl FnMut(&str, (usize, usize)) -> Result<ArrayBase<S, Ix2>, LoadError>,
+    ) -> Result<Self, LoadError> {
+        let (dim, ff_dim, heads) = (config.embed_dim, config.ff_dim, config.num_heads);
+        let patch_dim = config.patch_size * config.patch_size * 3;
+        let rope = Arc::new(config.rope_cache(config.grid()));
+
+        let patch_embed = PatchEmbed {
+            patch_size: config.patch_size,
+            embed_dim: dim,
+            weight: take("patch_embed.weight", (dim, patch_dim))?,
+            bias: if has("patch_embed.bias") { Some(take("patch_embed.bias", (dim, 1))?) } else { None },
+        };
+
+        let mut layers = Vec::with_capacity(config.num_layers);
+        for i in 0..config.num_layers {
+            let mut prefixed = |name: &str, shape| take(&format!("layers.{}.{}", i, name), shape);
+            let mut mha = attention(&mut prefixed, "mha", dim, heads, config.kv_heads())?;
+            mha.rope = Some(Arc::clone(&rope));
+            layers.push(TransformerLayer {
+                embed_dim: dim,
+                ff_dim,
+                num_heads: heads,
+                ln1: layer_norm(&mut prefixed, "ln1", dim)?,
+                ln2: layer_norm(&mut prefixed, "ln2", dim)?,
+                mha,
+                ffn: feed_forward(&mut prefixed, "ffn", dim, ff_dim)?,
+            });
+        }
+
+        let downsampler = match config.downsample {
+            DownsampleKind::None => Downsampler::None,
+            DownsampleKind::AvgPool => Downsampler::AvgPool,
+            DownsampleKind::Resampler => {
+                let mut prefixed = |name: &str, shape| take(&format!("resampler.{}", name), shape);
+                Downsampler::Resampler(Box::new(TokenResampler {
+                    num_queries: config.num_queries,
+                    dim,
+                    queries: prefixed("queries", (config.num_queries, dim))?,
+                    ln_q: layer_norm(&mut prefixed, "ln_q", dim)?,
+                    ln_kv: layer_norm(&mut prefixed, "ln_kv", dim)?,
+                    attn: attention(&mut prefixed, "attn", dim, heads, heads)?,
+                    ln_ffn: layer_norm(&mut prefixed, "ln_ffn", dim)?,
+                    ffn: feed_forward(&mut prefixed, "ffn", dim, ff_dim)?,
+                }))
+            }
+            DownsampleKind::Conv => Downsampler::Conv(ConvDownsampler {
+                dim,
+                weight: take("downsample.weight", (4 * dim, dim))?,
+                bias: take("downsample.bias", (1, dim))?,
+            }),
+        };
+
+        let glu = GLUProjection {
+            in_dim: dim,
+            out_dim: config.out_dim,
+            weight: take("glu.weight", (dim, 2 * config.out_dim))?,
+            bias: if has("glu.bias") { Some(take("glu.bias", (1, 2 * config.out_dim))?) } else { None },
+        };
+        Ok(VisionEncoder { config, patch_embed, layers, downsampler, glu })
+    }
+
+    /// 全部权重及其名称:
+    /// `patch_embed.{weight,bias}`、`layers.{i}.{ln1,ln2}.{gamma,beta}`、
+    /// `layers.{i}.mha.{wq,wk,wv,wo}` (GQA 时 wk/wv 为 (embed_dim, num_kv_heads * head_dim))、
+    /// `layers.{i}.ffn.{w1,b1,w2,b2}`、`glu.{weight,bias}`;
+    /// downsample 为 resampler 时另有 `resampler.queries`、`resampler.{ln_q,ln_kv,ln_ffn}.{gamma,beta}`、
+    /// `resampler.attn.{wq,wk,wv,wo}`、`resampler.ffn.{w1,b1,w2,b2}`, 为 conv 时有 `downsample.{weight,bias}`
+    pub fn named_weights(&self) -> Vec<(String, &ArrayBase<S, Ix2>)> {
+        let mut out = vec![("patch_embed.weight".to_string(), &self.patch_embed.weight)];
+        if let Some(bias) = &self.patch_embed.bias {
+            out.push(("patch_embed.bias".to_string(), bias));
+        }
+        for (i, layer) in self.layers.iter().enumerate() {
+            let named = [
+                ("ln1.gamma", &layer.ln1.gamma),
+                ("ln1.beta", &layer.ln1.beta),
+                ("ln2.gamma", &layer.ln2.gamma),
+                ("ln2.beta", &layer.ln2.beta),
+                ("mha.wq", &layer.mha.wq),
+                ("mha.wk", &layer.mha.wk),
+                ("mha.wv", &layer.mha.wv),
+                ("mha.wo", &layer.mha.wo),
+                ("ffn.w1", &layer.ffn.w1),
+                ("ffn.b1", &layer.ffn.b1),
+                ("ffn.w2", &layer.ffn.w2),
+                ("ffn.b2", &layer.ffn.b2),
+            ];
+            out.extend(named.into_iter().map(|(n, w)| (format!("layers.{}.{}", i, n), w)));
+        }
+        match &self.downsampler {
+            Downsampler::Resampler(r) => {
+                let named = [
+                    ("queries", &r.queries),
+                    ("ln_q.gamma", &r.ln_q.gamma),
+                    ("ln_q.beta", &r.ln_q.beta),
+                    ("ln_kv.gamma", &r.ln_kv.gamma),
+                    ("ln_kv.beta", &r.ln_kv.beta),
+                    ("ln_ffn.gamma", &r.ln_ffn.gamma),
+                    ("ln_ffn.beta", &r.ln_ffn.beta),
+                    ("attn.wq", &r.attn.wq),
+                    ("attn.wk", &r.attn.wk),
+                    ("attn.wv", &r.attn.wv),
+                    ("attn.wo", &r.attn.wo),
+                    ("ffn.w1", &r.ffn.w1),
+                    ("ffn.b1", &r.ffn.b1),
+                    ("ffn.w2", &r.ffn.w2),
+                    ("ffn.b2", &r.ffn.b2),
+                ];
+                out.extend(named.into_iter().map(|(n, w)| (format!("resampler.{}", n), w)));
+            }
+            Downsampler::Conv(conv) => {
+                out.push(("downsample.weight".to_string(), &conv.weight));
+                out.push(("downsample.bias".to_string(), &conv.bias));
+            }
+            Downsampler::None | Downsampler::AvgPool => {}
+        }
+        out.push(("glu.weight".to_string(), &self.glu.weight));
+        if let Some(bias) = &self.glu.bias {
+            out.push(("glu.bias".to_string(), bias));
+        }
+        out
+    }
+
+    /// 保存全部权重, 配置以 JSON 写入 metadata 的 `config` 字段
+    pub fn save_safetensors<P: AsRef<Path>>(&self, path: P) -> Result<(), LoadError> {
+        let weights = self.named_weights();
+        let buffers: Vec<Vec<u8>> = weights
+            .iter()
+            .map(|(_, w)| w.iter().flat_map(|v| v.to_le_bytes()).collect())
+            .collect();
+        let views = weights
+            .iter()
+            .zip(&buffers)
+            .map(|((name, w), buf)| Ok((name.clone(), TensorView::new(Dtype::F32, w.shape().to_vec(), buf)?)))
+            .collect::<Result<Vec<_>, SafeTensorError>>()?;
+
+        let metadata = HashMap::from([
+            (CONFIG_KEY.to_string(), serde_json::to_string(&self.config)?),
+            ("format".to_string(), FORMAT.to_string()),
+        ]);
+        safetensors::serialize_to_file(views, &Some(metadata), path.as_ref())?;
+        Ok(())
+    }
+
+    /// 保存为目录: `config.json` 加每个张量一个 `{name}.npy`
+    pub fn save_npy_dir<P: AsRef<Path>>(&self, dir: P) -> Result<(), LoadError> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join("config.json"), serde_json::to_string_pretty(&self.config)?)?;
+        for (name, w) in self.named_weights() {
+            write_npy(dir.join(format!("{}.npy", name)), w)?;
+        }
+        Ok(())
+    }
+
+    pub fn forward(&self, img: &Array3<f32>) -> Array2<f32> {
+        let mut x = self.patch_embed.forward(img);
+        for layer in &self.layers {
+            x = layer.forward(&x);
+        }
+        let x = self.downsampler.forward(&x, self.config.grid());
+        self.glu.forward(&x)
+    }
+
+    // 记录 patch_embed / layer{i}.* / downsample (启用时) / glu.*
+    pub fn forward_captured(&self, img: &Array3<f32>, capture: &mut ActivationCapture) -> Array2<f32> {
+        let mut x = self.patch_embed.forward_captured(img, "patch_embed", capture);
+        for (i, layer) in self.layers.iter().enumerate() {
+            x = layer.forward_captured(&x, &format!("layer{}", i), capture);
+        }
+        if !matches!(self.downsampler, Downsampler::None) {
+            x = self.downsampler.forward(&x, self.config.grid());
+            capture.record("downsample", &x);
+        }
+        self.glu.forward_captured(&x, "glu", capture)
+    }
+
+    pub fn forward_batch(&self, images: &[Array3<f32>]) -> Vec<Array2<f32>> {
+        images.par_iter().map(|img| self.forward(img)).collect()
+    }
+
+    /// 逐帧并行编码 (如 `ImageProcessor::preprocess_video` 的输出), 再按 temporal 处理时间维
+    pub fn forward_video(&self, frames: &[Array3<f32>], temporal: Temporal) -> Vec<Array2<f32>> {
+        apply_temporal(self.forward_batch(frames), temporal)
+    }
+
+    /// 原生分辨率输入 (见 `ImageProcessor::preprocess_native`), H 和 W 须为 patch_size 的倍数;
+    /// 网格与配置不同时按实际网格重建 RoPE 表
+    pub fn forward_native(&self, img: &Array3<f32>) -> Array2<f32> {
+        let grid = self.patch_grid(img);
+        let rope = self.rope_for_grid(grid);
+
+        let mut x = self.patch_embed.forward(img);
+        for layer in &self.layers {
+            x = layer.forward_with_rope(&x, rope.as_deref());
+        }
+        let x = self.downsampler.forward(&x, grid);
+        self.glu.forward(&x)
+    }
+
+    /// 尺寸各不相同的原生分辨率图像, 逐张并行编码
+    pub fn forward_native_batch(&self, images: &[Array3<f32>]) -> Vec<Array2<f32>> {
+        images.par_iter().map(|img| self.forward_native(img)).collect()
+    }
+
+    /// 与 `forward_native_batch` 结果相同, 但所有图像的 token 打包成一个序列,
+    /// 每层只做一次 (段内的) 注意力计算, 不需要按最大尺寸填充
+    pub fn forward_packed(&self, images: &[Array3<f32>]) -> Vec<Array2<f32>> {
+        if images.is_empty() {
+            return Vec::new();
+        }
+        let grids: Vec<_> = images.iter().map(|img| self.patch_grid(img)).collect();
+        let tokens: Vec<_> = images.par_iter().map(|img| self.patch_embed.forward(img)).collect();
+        let (mut x, cu_seqlens) = packing::pack(&tokens);
+
+        let ropes: Option<Vec<_>> = grids.iter().map(|&grid| self.rope_for_grid(grid)).collect();
+        let rope = ropes.map(|ropes| {
+            let parts: Vec<_> = ropes.iter().zip(&grids).map(|(r, (h, w))| (r.as_ref(), h * w)).collect();
+            RopeCache::packed(&parts)
+        });
+        for layer in &self.layers {
+            x = layer.forward_varlen(&x, &cu_seqlens, rope.as_ref());
+        }
+
+        packing::unpack(&x, &cu_seqlens)
+            .into_par_iter()
+            .zip(grids)
+            .map(|(x, grid)| self.glu.forward(&self.downsampler.forward(&x, grid)))
+            .collect()
+    }
+
+    fn patch_grid(&self, img: &Array3<f32>) -> (usize, usize) {
+        let ps = self.config.patch_size;
+        let (h, w) = (img.shape()[1], img.shape()[2]);
+        assert!(
+            h.is_multiple_of(ps) && w.is_multiple_of(ps),
+            "image {}x{} is not a multiple of patch_size {}",
+            h,
+            w,
+            ps
+        );
+        (h / ps, w / ps)
+    }
+
+    // 配置网格直接用各层共享的表, 其余网格沿用其 RopeConfig 重新计算
+    fn rope_for_grid(&self, grid: (usize, usize)) -> Option<Arc<RopeCache>> {
+        let rope = self.layers.first()?.mha.rope.as_ref()?;
+        if grid == self.config.grid() {
+            return Some(Arc::clone(rope));
+        }
+        Some(Arc::new(self.config.rope_cache_with(grid, rope.config.clone())))
+    }
+
+    /// 编码 `ImageProcessor::preprocess_tiled` 的全部块和缩略图, 保留每块的网格位置
+    pub fn forward_tiled(&self, tiled: &TiledImage) -> TiledEmbeddings {
+        let (tiles, thumbnail) = rayon::join(
+            || {
+                tiled
+                    .tiles
+                    .par_iter()
+                    .map(|t| TileEmbedding { row: t.row, col: t.col, embedding: self.forward(&t.image) })
+                    .collect()
+            },
+            || tiled.thumbnail.as_ref().map(|img| self.forward(img)),
+        );
+        TiledEmbeddings { grid: tiled.grid, tiles, thumbnail }
+    }
+}
+
+fn layer_norm<S, F>(take: &mut F, prefix: &str, dim: usize) -> Result<LayerNorm<S>, LoadError>
+where
+    S: Data<Elem = f32>,
+    F: FnMut(&str, (usize, usize)) -> Result<ArrayBase<S, Ix2>, LoadError>,
+{
+    Ok(LayerNorm {
+        epsilon: 1e-5,
+        gamma: take(&format!("{}.gamma", prefix), (1, dim))?,
+        beta: take(&format!("{}.beta", prefix), (1, dim))?,
+    })
+}
+
+fn attention<S, F>(
+    take: &mut F,
+    prefix: &str,
+    dim: usize,
+    num_heads: usize,
+    num_kv_heads: usize,
+) -> Result<MultiHeadAttention<S>, LoadError>
+where
+    S: Data<Elem = f32>,
+    F: FnMut(&str, (usize, usize)) -> Result<ArrayBase<S, Ix2>, LoadError>,
+{
+    let head_dim = dim / num_heads;
+    Ok(MultiHeadAttention {
+        num_heads,
+        num_kv_heads,
+        head_dim,
+        wq: take(&format!("{}.wq", prefix), (dim, num_heads * head_dim))?,
+        wk: take(&format!("{}.wk", prefix), (dim, num_kv_heads * head_dim))?,
+        wv: take(&format!("{}.wv", prefix), (dim, num_kv_heads * head_dim))?,
+        wo: take(&format!("{}.wo", prefix), (num_heads * head_dim, dim))?,
+        rope: None,
+    })
+}
+
+fn feed_forward<S, F>(take: &mut F, prefix: &str, dim: usize, ff_dim: usize) -> Result<FeedForward<S>, LoadError>
+where
+    S: Data<Elem = f32>,
+    F: FnMut(&str, (usize, usize)) -> Result<ArrayBase<S, Ix2>, LoadError>,
+{
+    Ok(FeedForward {
+        w1: take(&format!("{}.w1", prefix), (dim, ff_dim))?,
+        w2: take(&format!("{}.w2", prefix), (ff_dim, dim))?,
+        b1: take(&format!("{}.b1", prefix), (1, ff_dim))?,
+        b2: take(&format!("{}.b2", prefix), (1, dim))?,
+    })
+}
+
+// 读取 save_safetensors 写入 metadata 的配置
+pub(crate) fn config_from_header(bytes: &[u8]) -> Result<EncoderConfig, LoadError> {
+    let (_, metadata) = SafeTensors::read_metadata(bytes)?;
+    let config = metadata
+        .metadata()
+        .as_ref()
+        .and_then(|m| m.get(CONFIG_KEY))
+        .ok_or(LoadError::MissingConfig)?;
+    Ok(serde_json::from_str(config)?)
+}
+
+// 一维张量在元素数一致且目标有一维为 1 时可以按目标形状展开
+pub(crate) fn check_shape(name: &str, actual: Vec<usize>, shape: (usize, usize)) -> Result<(), LoadError> {
+    let matches = actual == [shape.0, shape.1]
+        || (actual.len() == 1 && (shape.0 == 1 || shape.1 == 1) && actual[0] == shape.0 * shape.1);
+    if matches {
+        Ok(())
+    } else {
+        Err(LoadError::Shape {
+            name: name.to_string(),
+            expected: vec![shape.0, shape.1],
+            actual,
+        })
+    }
+}
+
+// 读取 F32 张量
+fn take(tensors: &SafeTensors, name: &str, shape: (usize, usize)) -> Result<Array2<f32>, LoadError> {
+    let view = tensors.tensor(name)?;
+    if view.dtype() != Dtype::F32 {
+        return Err(LoadError::Dtype { name: name.to_string(), dtype: view.dtype() });
+    }
+
+    check_shape(name, view.shape().to_vec(), shape)?;
+
+    let data: Vec<f32> = view
+        .data()
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    Ok(Array2::from_shape_vec(shape, data).unwrap())
+}
@@ -1,30 +1,37 @@
-use ndarray::{Array2, Axis, s};
+use ndarray::{Array2, ArrayBase, Axis, Data, Ix2, OwnedRepr, Zip, s};
 use rayon::prelude::*;
-use std::simd::{Simd};
-use std::simd::num::SimdFloat;
-use ndarray_rand::RandomExt;
-use rand_distr::Uniform;
+use rand::Rng;
+use crate::capture::ActivationCapture;
+use crate::init::Initializer;
+use crate::simd;
 
-pub struct GLUProjection {
+pub struct GLUProjection<S: Data<Elem = f32> = OwnedRepr<f32>> {
     pub in_dim: usize,
     pub out_dim: usize,
-    pub weight: Array2<f32>,      // [in_dim, 2*out_dim]
-    pub bias: Option<Array2<f32>> // [1, 2*out_dim]
+    pub weight: ArrayBase<S, Ix2>,      // [in_dim, 2*out_dim]
+    pub bias: Option<ArrayBase<S, Ix2>> // [1, 2*out_dim]
 }
 
 impl GLUProjection {
     pub fn new(in_dim: usize, out_dim: usize) -> Self {
-        let fan_in = in_dim;
-        let fan_out = 2 * out_dim;
-        let limit = (6.0 / (fan_in + fan_out) as f32).sqrt();
-        let dist = Uniform::new(-limit, limit);
+        Self::new_with_rng(in_dim, out_dim, &mut rand::thread_rng())
+    }
 
-        let weight = Array2::random((in_dim, 2 * out_dim), dist);
+    pub fn new_with_rng<R: Rng + ?Sized>(in_dim: usize, out_dim: usize, rng: &mut R) -> Self {
+        let weight = Initializer::Xavier.linear(in_dim, 2 * out_dim, rng);
         let bias = Some(Array2::zeros((1, 2 * out_dim)));
 
         GLUProjection { in_dim, out_dim, weight, bias }
     }
 
+    pub fn parameters_mut(&mut self) -> Vec<&mut Array2<f32>> {
+        let mut params = vec![&mut self.weight];
+        params.extend(self.bias.as_mut());
+        params
+    }
+}
+
+impl<S: Data<Elem = f32> + Sync> GLUProjection<S> {
     pub fn forward(&self, x: &Array2<f32>) -> Array2<f32> {
         let mut projected = x.dot(&self.weight);
         if let Some(bias) = &self.bias {
@@ -37,6 +44,54 @@ impl GLUProjection {
         value_part * gate_part
     }
 
+    // 与 forward 相同, 额外记录 {name}.projected 和 {name}
+    pub fn forward_captured(&self, x: &Array2<f32>, name: &str, capture: &mut ActivationCapture) -> Array2<f32> {
+        let mut projected = x.dot(&self.weight);
+        if let Some(bias) = &self.bias {
+            projected += bias;
+        }
+        capture.record(&format!("{}.projected", name), &projected);
+
+        let value_part = projected.slice(s![.., 0..self.out_dim]).to_owned();
+        let gate_part = projected.slice(s![.., self.out_dim..]).mapv(|v| v.max(0.0));
+        let out = value_part * gate_part;
+        capture.record(name, &out);
+        out
+    }
+
+    pub fn forward_train(&self, x: &Array2<f32>) -> (Array2<f32>, GLUCache) {
+        let mut projected = x.dot(&self.weight);
+        if let Some(bias) = &self.bias {
+            projected += bias;
+        }
+
+        let value_part = projected.slice(s![.., 0..self.out_dim]).to_owned();
+        let gate_part = projected.slice(s![.., self.out_dim..]).mapv(|v| v.max(0.0));
+        (value_part * gate_part, GLUCache { x: x.clone(), projected })
+    }
+
+    pub fn backward(&self, cache: &GLUCache, grad_out: &Array2<f32>) -> (Array2<f32>, GLUGrads) {
+        let value_part = cache.projected.slice(s![.., 0..self.out_dim]);
+        let gate_pre = cache.projected.slice(s![.., self.out_dim..]);
+
+        let mut d_projected = Array2::<f32>::zeros(cache.projected.raw_dim());
+        d_projected
+            .slice_mut(s![.., 0..self.out_dim])
+            .assign(&(grad_out * &gate_pre.mapv(|v| v.max(0.0))));
+        // ReLU 门控: 只有 gate > 0 的位置有梯度
+        Zip::from(d_projected.slice_mut(s![.., self.out_dim..]))
+            .and(grad_out)
+            .and(&value_part)
+            .and(&gate_pre)
+            .for_each(|d, &g, &v, &pre| *d = if pre > 0.0 { g * v } else { 0.0 });
+
+        let grads = GLUGrads {
+            weight: cache.x.t().dot(&d_projected),
+            bias: self.bias.as_ref().map(|_| d_projected.sum_axis(Axis(0)).insert_axis(Axis(0))),
+        };
+        (d_projected.dot(&self.weight.t()), grads)
+    }
+
     pub fn forward_rayon(&self, x: &Array2<f32>) -> Array2<f32> {
         let mut projected = x.dot(&self.weight);
         if let Some(bias) = &self.bias {
@@ -68,6 +123,25 @@ impl GLUProjection {
     }
 }
 
+pub struct GLUCache {
+    x: Array2<f32>,
+    projected: Array2<f32>,
+}
+
+pub struct GLUGrads {
+    pub weight: Array2<f32>,
+    pub bias: Option<Array2<f32>>,
+}
+
+impl GLUGrads {
+    // 与 GLUProjection::parameters_mut 顺序一致
+    pub fn tensors(&self) -> Vec<&Array2<f32>> {
+        let mut tensors = vec![&self.weight];
+        tensors.extend(self.bias.as_ref());
+        tensors
+    }
+}
+
 // 标准 GLU 激活函数
 fn activate_glu(row: ndarray::Array1<f32>, out_dim: usize) -> ndarray::Array1<f32> {
     let value = row.slice(s![0..out_dim]).to_owned();
@@ -75,34 +149,12 @@ fn activate_glu(row: ndarray::Array1<f32>, out_dim: usize) -> ndarray::Array1<f3
     value * gate
 }
 
-// SIMD
+// SIMD, 后端由 simd::backend() 在运行时选择
 fn activate_glu_simd(row: ndarray::Array1<f32>, out_dim: usize) -> ndarray::Array1<f32> {
-    const LANES: usize = 8;
-    type SimdType = Simd<f32, LANES>;
-
-    let value = row.slice(s![0..out_dim]);
-    let gate = row.slice(s![out_dim..]);
-
-    let mut out = Vec::with_capacity(out_dim);
-
-    let value_chunks = value.as_slice().unwrap().chunks_exact(LANES);
-    let gate_chunks = gate.as_slice().unwrap().chunks_exact(LANES);
-    let remainder_v = value_chunks.remainder();
-    let remainder_g = gate_chunks.remainder();
-
-    for (vc, gc) in value_chunks.zip(gate_chunks) {
-        let v_simd = SimdType::from_slice(vc);
-        let g_simd = SimdType::from_slice(gc);
-        let relu_gate = g_simd.simd_max(SimdType::splat(0.0));
-        let out_simd = v_simd * relu_gate;
-        out.extend_from_slice(&out_simd.to_array());
-    }
-
-    for i in 0..remainder_v.len() {
-        let v = remainder_v[i];
-        let g = remainder_g[i].max(0.0);
-        out.push(v * g);
-    }
+    let row = row.as_slice().unwrap();
+    let (value, gate) = (&row[..out_dim], &row[out_dim..2 * out_dim]);
 
+    let mut out = vec![0.0; out_dim];
+    simd::glu(value, gate, &mut out);
     ndarray::Array1::from(out)
 }
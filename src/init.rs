@@ -0,0 +1,56 @@
+// src/init.rs
+//
+// 权重初始化方案, 随机数由调用方显式传入, 用 StdRng::seed_from_u64 即可复现
+
+use ndarray::Array2;
+use ndarray_rand::RandomExt;
+use rand::Rng;
+use rand_distr::{Distribution, Normal, Uniform};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Initializer {
+    Zeros,
+    /// Glorot/Xavier 均匀分布, 范围 ±sqrt(6 / (fan_in + fan_out))
+    Xavier,
+    /// He/Kaiming 正态分布, std = sqrt(2 / fan_in), 适用于 ReLU/GELU 前的投影
+    Kaiming,
+    /// 截断在 ±2 std 的正态分布 (ViT 的 trunc_normal_(std=0.02))
+    TruncatedNormal { std: f32 },
+}
+
+impl Initializer {
+    /// fan_in / fan_out 单独给出, 因为各模块的权重布局不同 ((in, out) 或 (out, in))
+    pub fn init<R: Rng + ?Sized>(
+        &self,
+        shape: (usize, usize),
+        fan_in: usize,
+        fan_out: usize,
+        rng: &mut R,
+    ) -> Array2<f32> {
+        match *self {
+            Initializer::Zeros => Array2::zeros(shape),
+            Initializer::Xavier => {
+                let limit = (6.0 / (fan_in + fan_out) as f32).sqrt();
+                Array2::random_using(shape, Uniform::new_inclusive(-limit, limit), rng)
+            }
+            Initializer::Kaiming => {
+                let std = (2.0 / fan_in as f32).sqrt();
+                Array2::random_using(shape, Normal::new(0.0, std).unwrap(), rng)
+            }
+            Initializer::TruncatedNormal { std } => {
+                let normal = Normal::new(0.0, std).unwrap();
+                Array2::from_shape_simple_fn(shape, || loop {
+                    let v: f32 = normal.sample(rng);
+                    if v.abs() <= 2.0 * std {
+                        break v;
+                    }
+                })
+            }
+        }
+    }
+
+    /// (fan_in, fan_out) 布局的线性层权重
+    pub fn linear<R: Rng + ?Sized>(&self, fan_in: usize, fan_out: usize, rng: &mut R) -> Array2<f32> {
+        self.init((fan_in, fan_out), fan_in, fan_out, rng)
+    }
+}
@@ -1,7 +1,22 @@
-#![feature(portable_simd)]
 pub mod processor;
+pub mod stream;
+pub mod augment;
+pub mod tiling;
+pub mod video;
 pub mod patch_embed;
 pub mod rope;
 pub mod transformer;
+pub mod packing;
 pub mod patch_dropout;
-pub mod glu_projection;
\ No newline at end of file
+pub mod glu_projection;
+pub mod resampler;
+pub mod optim;
+pub mod init;
+pub mod capture;
+pub mod encoder;
+// 按小端 f32 直接解释文件内容
+#[cfg(target_endian = "little")]
+pub mod mmap;
+pub mod simd;
+#[cfg(feature = "server")]
+pub mod server;
@@ -0,0 +1,70 @@
+// src/mmap.rs
+//
+// 以只读内存映射打开 safetensors 文件, 编码器的权重直接借用映射的页面 (ArrayView2):
+// 打开时只解析文件头, 权重在首次访问时才从 page cache 读入, 多个进程映射同一文件时共享物理内存
+//
+//   let weights = MappedWeights::open("model.safetensors")?;
+//   let encoder = weights.encoder()?;   // MappedEncoder<'_>, 与 VisionEncoder 的推理接口相同
+
+use memmap2::Mmap;
+use ndarray::{ArrayView2, ViewRepr};
+use safetensors::{Dtype, SafeTensors};
+use std::fs::File;
+use std::path::Path;
+use crate::encoder::{check_shape, config_from_header, EncoderConfig, LoadError, VisionEncoder};
+
+/// 借用 `MappedWeights` 的编码器
+pub type MappedEncoder<'a> = VisionEncoder<ViewRepr<&'a f32>>;
+
+pub struct MappedWeights {
+    mmap: Mmap,
+}
+
+impl MappedWeights {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let file = File::open(path)?;
+        // 映射期间文件被截断或原地改写是未定义行为, 部署时权重文件应只读, 更新时换新文件
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(MappedWeights { mmap })
+    }
+
+    /// 映射的整个文件内容, 借用的权重都指向这段内存
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    /// 文件头 metadata 中的配置, 仅 `save_safetensors` 写出的文件带有
+    pub fn config(&self) -> Result<EncoderConfig, LoadError> {
+        config_from_header(&self.mmap)
+    }
+
+    pub fn encoder(&self) -> Result<MappedEncoder<'_>, LoadError> {
+        self.encoder_with_config(self.config()?)
+    }
+
+    /// 张量命名和形状规则与 `VisionEncoder::load_safetensors` 相同
+    pub fn encoder_with_config(&self, config: EncoderConfig) -> Result<MappedEncoder<'_>, LoadError> {
+        let tensors = SafeTensors::deserialize(&self.mmap)?;
+        let names = tensors.names();
+        VisionEncoder::from_source(config, |name| names.iter().any(|n| n.as_str() == name), |name, shape| {
+            view(&tensors, name, shape)
+        })
+    }
+}
+
+// 零拷贝地把 F32 张量解释为 (小端) f32 切片
+fn view<'a>(tensors: &SafeTensors<'a>, name: &str, shape: (usize, usize)) -> Result<ArrayView2<'a, f32>, LoadError> {
+    let tensor = tensors.tensor(name)?;
+    if tensor.dtype() != Dtype::F32 {
+        return Err(LoadError::Dtype { name: name.to_string(), dtype: tensor.dtype() });
+    }
+    let data = tensor.data();
+    check_shape(name, tensor.shape().to_vec(), shape)?;
+
+    // safetensors 写出的数据区在 8 字节对齐的文件头之后, 其他工具写出的文件可能不满足 4 字节对齐
+    let (head, floats, tail) = unsafe { data.align_to::<f32>() };
+    if !head.is_empty() || !tail.is_empty() {
+        return Err(LoadError::Unaligned { name: name.to_string() });
+    }
+    Ok(ArrayView2::from_shape(shape, floats).unwrap())
+}
@@ -0,0 +1,94 @@
+// src/optim.rs
+//
+// 参数更新。各模块的 parameters_mut() 与对应 *Grads::tensors() 顺序一致, 按位置配对:
+//
+//   let (out, cache) = glu.forward_train(&x);
+//   let (_, grads) = glu.backward(&cache, &grad_out);
+//   opt.step(glu.parameters_mut(), grads.tensors());
+//
+// 优化器按位置保存动量等状态, 同一个优化器实例每次应传入相同顺序的参数
+
+use ndarray::{Array2, Zip};
+
+pub struct Sgd {
+    pub lr: f32,
+    pub momentum: f32,
+    pub weight_decay: f32,
+    velocity: Vec<Array2<f32>>,
+}
+
+impl Sgd {
+    pub fn new(lr: f32) -> Self {
+        Sgd { lr, momentum: 0.0, weight_decay: 0.0, velocity: Vec::new() }
+    }
+
+    pub fn with_momentum(lr: f32, momentum: f32) -> Self {
+        Sgd { momentum, ..Sgd::new(lr) }
+    }
+
+    pub fn step(&mut self, params: Vec<&mut Array2<f32>>, grads: Vec<&Array2<f32>>) {
+        assert_eq!(params.len(), grads.len(), "parameter / gradient count mismatch");
+        if self.velocity.is_empty() {
+            self.velocity = grads.iter().map(|g| Array2::zeros(g.raw_dim())).collect();
+        }
+
+        for ((param, grad), vel) in params.into_iter().zip(grads).zip(&mut self.velocity) {
+            let (lr, momentum, wd) = (self.lr, self.momentum, self.weight_decay);
+            Zip::from(param).and(grad).and(vel).for_each(|p, &g, v| {
+                let g = g + wd * *p;
+                *v = momentum * *v + g;
+                *p -= lr * *v;
+            });
+        }
+    }
+}
+
+/// Adam + 解耦的权重衰减 (Loshchilov & Hutter)
+pub struct AdamW {
+    pub lr: f32,
+    pub beta1: f32,
+    pub beta2: f32,
+    pub eps: f32,
+    pub weight_decay: f32,
+    step: i32,
+    m: Vec<Array2<f32>>,
+    v: Vec<Array2<f32>>,
+}
+
+impl AdamW {
+    pub fn new(lr: f32) -> Self {
+        AdamW {
+            lr,
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+            weight_decay: 0.01,
+            step: 0,
+            m: Vec::new(),
+            v: Vec::new(),
+        }
+    }
+
+    pub fn step(&mut self, params: Vec<&mut Array2<f32>>, grads: Vec<&Array2<f32>>) {
+        assert_eq!(params.len(), grads.len(), "parameter / gradient count mismatch");
+        if self.m.is_empty() {
+            self.m = grads.iter().map(|g| Array2::zeros(g.raw_dim())).collect();
+            self.v = grads.iter().map(|g| Array2::zeros(g.raw_dim())).collect();
+        }
+
+        self.step += 1;
+        let (b1, b2, eps) = (self.beta1, self.beta2, self.eps);
+        let bias1 = 1.0 - b1.powi(self.step);
+        let bias2 = 1.0 - b2.powi(self.step);
+        let (lr, wd) = (self.lr, self.weight_decay);
+
+        for (((param, grad), m), v) in params.into_iter().zip(grads).zip(&mut self.m).zip(&mut self.v) {
+            Zip::from(param).and(grad).and(m).and(v).for_each(|p, &g, m, v| {
+                *m = b1 * *m + (1.0 - b1) * g;
+                *v = b2 * *v + (1.0 - b2) * g * g;
+                let update = (*m / bias1) / ((*v / bias2).sqrt() + eps);
+                *p -= lr * (update + wd * *p);
+            });
+        }
+    }
+}
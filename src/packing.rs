@@ -0,0 +1,43 @@
+// src/packing.rs
+//
+// 序列打包: 把 patch 数不同的多张图的 token 按行拼成一个 Array2, 用 cu_seqlens 记录每段的边界,
+// 注意力只在段内计算 (MultiHeadAttention::forward_varlen), 不需要填充
+//
+//   let (packed, cu_seqlens) = pack(&tokens);           // cu_seqlens = [0, n0, n0 + n1, ...]
+//   let out = layer.forward_varlen(&packed, &cu_seqlens, rope);
+//   let per_image = unpack(&out, &cu_seqlens);
+
+use ndarray::{Array2, Axis, s};
+
+/// 按行拼接, 返回 (打包后的序列, cu_seqlens)
+pub fn pack(seqs: &[Array2<f32>]) -> (Array2<f32>, Vec<usize>) {
+    assert!(!seqs.is_empty(), "cannot pack an empty batch");
+    let views: Vec<_> = seqs.iter().map(|x| x.view()).collect();
+    let packed = ndarray::concatenate(Axis(0), &views).expect("packed sequences must share a feature dim");
+    (packed, cu_seqlens(&seqs.iter().map(|x| x.shape()[0]).collect::<Vec<_>>()))
+}
+
+/// 按 cu_seqlens 把打包的输出拆回每个序列
+pub fn unpack(packed: &Array2<f32>, cu_seqlens: &[usize]) -> Vec<Array2<f32>> {
+    cu_seqlens.windows(2).map(|w| packed.slice(s![w[0]..w[1], ..]).to_owned()).collect()
+}
+
+/// 各段长度 -> 累计偏移 [0, len0, len0 + len1, ..., total]
+pub fn cu_seqlens(lengths: &[usize]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(lengths.len() + 1);
+    offsets.push(0);
+    for len in lengths {
+        offsets.push(offsets.last().unwrap() + len);
+    }
+    offsets
+}
+
+/// 与 cu_seqlens 等价的 (total, total) 块对角掩码, 可直接传给 `MultiHeadAttention::forward_cross`
+pub fn block_diagonal_mask(cu_seqlens: &[usize]) -> Array2<bool> {
+    let total = *cu_seqlens.last().unwrap_or(&0);
+    let mut mask = Array2::from_elem((total, total), false);
+    for w in cu_seqlens.windows(2) {
+        mask.slice_mut(s![w[0]..w[1], w[0]..w[1]]).fill(true);
+    }
+    mask
+}
@@ -2,9 +2,10 @@
 
 use ndarray::{Array2, Axis, concatenate, s};
 use rand::seq::index::sample;
+use rand::seq::SliceRandom;
 use rand::thread_rng;
 use rayon::prelude::*;
-use std::simd::Simd;  
+use crate::simd;
 
 pub struct PatchDropout {
     pub keep_ratio: f32,
@@ -125,19 +126,168 @@ impl PatchDropout {
     }
 }
 
-// simd加速拷贝 每次处理LANES个float
-fn copy_row_simd(input: &[f32]) -> Vec<f32> {
-    const LANES: usize = 8;
-    type SimdType = Simd<f32, LANES>;
+// 结构化的 token 裁剪策略, 均返回 (保留的 token, 每个 token 在 x 中的原始下标)。
+// cls_token 为 true 时第 0 行始终保留且不参与裁剪/合并, 保留数量与 forward 一样由 keep_ratio 决定
+impl PatchDropout {
+    // cls 之外需要保留的 patch 数
+    fn patch_keep_count(&self, n: usize) -> usize {
+        let keep_count = ((n as f32) * self.keep_ratio).round() as usize;
+        keep_count.saturating_sub(self.cls_token as usize)
+    }
 
-    let mut output = Vec::with_capacity(input.len());
-    let chunks = input.chunks_exact(LANES);
-    let remainder = chunks.remainder();
+    fn gather(&self, x: &Array2<f32>, patch_indices: &[usize]) -> (Array2<f32>, Vec<usize>) {
+        let offset = self.cls_token as usize;
+        let mut indices = Vec::with_capacity(patch_indices.len() + offset);
+        if self.cls_token {
+            indices.push(0);
+        }
+        indices.extend(patch_indices.iter().map(|&i| i + offset));
+        (x.select(Axis(0), &indices), indices)
+    }
 
-    for chunk in chunks {
-        let v = SimdType::from_slice(chunk);
-        output.extend_from_slice(&v.to_array());
+    // 没有 patch token (空输入或只有 cls) 时无可丢弃, 原样返回
+    fn unchanged(&self, x: &Array2<f32>) -> (Array2<f32>, Vec<usize>) {
+        (x.clone(), (0..x.nrows()).collect())
     }
-    output.extend_from_slice(remainder);
+
+    /// 以 block_size x block_size 的块为单位随机丢弃, grid 为 patch 网格 (行, 列)。
+    /// 按随机顺序整块保留直到达到保留数量, 因此实际保留数可能略多于 keep_ratio
+    pub fn forward_block(
+        &self,
+        x: &Array2<f32>,
+        grid: (usize, usize),
+        block_size: usize,
+    ) -> (Array2<f32>, Vec<usize>) {
+        assert!(block_size > 0, "block_size must be positive");
+        let (gh, gw) = grid;
+        let offset = self.cls_token as usize;
+        if x.nrows() <= offset {
+            return self.unchanged(x);
+        }
+        assert_eq!(x.shape()[0] - offset, gh * gw, "patch count must equal grid_h * grid_w");
+        let target = self.patch_keep_count(x.shape()[0]);
+
+        let (bh, bw) = (gh.div_ceil(block_size), gw.div_ceil(block_size));
+        let mut blocks: Vec<usize> = (0..bh * bw).collect();
+        blocks.shuffle(&mut thread_rng());
+
+        let mut kept = Vec::with_capacity(target + block_size * block_size);
+        for block in blocks {
+            if kept.len() >= target {
+                break;
+            }
+            let (bi, bj) = (block / bw, block % bw);
+            for i in bi * block_size..((bi + 1) * block_size).min(gh) {
+                for j in bj * block_size..((bj + 1) * block_size).min(gw) {
+                    kept.push(i * gw + j);
+                }
+            }
+        }
+        kept.sort_unstable();
+        self.gather(x, &kept)
+    }
+
+    /// 按外部给定的显著性分数保留 top-k patch, scores 与 cls 之外的 patch 一一对应, 输出保持原始顺序
+    pub fn forward_saliency(&self, x: &Array2<f32>, scores: &[f32]) -> (Array2<f32>, Vec<usize>) {
+        let offset = self.cls_token as usize;
+        if x.nrows() <= offset {
+            return self.unchanged(x);
+        }
+        assert_eq!(scores.len(), x.shape()[0] - offset, "one score per patch token");
+        let target = self.patch_keep_count(x.shape()[0]).min(scores.len());
+
+        let mut order: Vec<usize> = (0..scores.len()).collect();
+        order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+        let mut kept = order[..target].to_vec();
+        kept.sort_unstable();
+        self.gather(x, &kept)
+    }
+
+    /// ToMe 式二分图合并: patch 交替分为 A/B 两组, A 中每个 token 找余弦相似度最高的 B token,
+    /// 每轮合并相似度最高的若干对, 直到剩余数量达到 keep_ratio。
+    /// 合并后的 token 为其覆盖的所有原始 token 的均值, 返回每个输出 token 覆盖的原始下标 (升序)
+    pub fn forward_merge(&self, x: &Array2<f32>) -> (Array2<f32>, Vec<Vec<usize>>) {
+        let offset = self.cls_token as usize;
+        if x.nrows() <= offset {
+            return (x.clone(), (0..x.nrows()).map(|i| vec![i]).collect());
+        }
+        let target = self.patch_keep_count(x.shape()[0]).max(1);
+
+        let mut sources: Vec<Vec<usize>> = (offset..x.shape()[0]).map(|i| vec![i]).collect();
+        let mut tokens = x.slice(s![offset.., ..]).to_owned();
+        while sources.len() > target && sources.len() > 1 {
+            let (a, b): (Vec<usize>, Vec<usize>) = (0..sources.len()).partition(|i| i % 2 == 0);
+            let r = (sources.len() - target).min(a.len());
+
+            let normed = normalize_rows(&tokens);
+            let sim = normed.select(Axis(0), &a).dot(&normed.select(Axis(0), &b).t());
+            let mut edges: Vec<(usize, usize, f32)> = sim
+                .outer_iter()
+                .enumerate()
+                .map(|(ai, row)| {
+                    let (bi, &best) = row
+                        .iter()
+                        .enumerate()
+                        .max_by(|p, q| p.1.total_cmp(q.1))
+                        .unwrap();
+                    (a[ai], b[bi], best)
+                })
+                .collect();
+            edges.sort_by(|p, q| q.2.total_cmp(&p.2));
+
+            let mut merged_into = vec![None; sources.len()];
+            for &(src, dst, _) in &edges[..r] {
+                merged_into[src] = Some(dst);
+            }
+            let mut next = Vec::with_capacity(sources.len() - r);
+            for (i, merged) in merged_into.iter().enumerate() {
+                if merged.is_none() {
+                    let mut group = sources[i].clone();
+                    group.extend(
+                        merged_into
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, m)| **m == Some(i))
+                            .flat_map(|(j, _)| sources[j].iter().copied()),
+                    );
+                    group.sort_unstable();
+                    next.push(group);
+                }
+            }
+            next.sort_by_key(|g| g[0]);
+
+            sources = next;
+            tokens = Array2::zeros((sources.len(), x.shape()[1]));
+            for (mut row, group) in tokens.outer_iter_mut().zip(&sources) {
+                for &i in group {
+                    row += &x.row(i);
+                }
+                row /= group.len() as f32;
+            }
+        }
+
+        if self.cls_token {
+            sources.insert(0, vec![0]);
+            tokens = concatenate(Axis(0), &[x.slice(s![0..1, ..]), tokens.view()]).unwrap();
+        }
+        (tokens, sources)
+    }
+}
+
+fn normalize_rows(x: &Array2<f32>) -> Array2<f32> {
+    let mut out = x.clone();
+    for mut row in out.outer_iter_mut() {
+        let norm = row.dot(&row).sqrt();
+        if norm > 0.0 {
+            row /= norm;
+        }
+    }
+    out
+}
+
+// simd加速拷贝, 后端由 simd::backend() 在运行时选择
+fn copy_row_simd(input: &[f32]) -> Vec<f32> {
+    let mut output = vec![0.0; input.len()];
+    simd::copy(input, &mut output);
     output
 }
\ No newline at end of file
@@ -1,27 +1,80 @@
-use ndarray::{Array2, Array3, s};
+use ndarray::{Array2, Array3, ArrayBase, Axis, Data, Ix2, OwnedRepr, s};
 use rayon::prelude::*;
-use std::simd::{Simd};
-use ndarray_rand::RandomExt;
-use rand_distr::StandardNormal;
+use rand::Rng;
+use crate::capture::ActivationCapture;
+use crate::init::Initializer;
+use crate::simd;
 
-pub struct PatchEmbed {
+pub struct PatchEmbed<S: Data<Elem = f32> = OwnedRepr<f32>> {
     pub patch_size: usize,
     pub embed_dim: usize,
-    pub weight: Array2<f32>, // shape: [embed_dim, patch_dim]
-    pub bias: Option<Array2<f32>>, // shape: [embed_dim, 1]
+    pub weight: ArrayBase<S, Ix2>, // shape: [embed_dim, patch_dim]
+    pub bias: Option<ArrayBase<S, Ix2>>, // shape: [embed_dim, 1]
 }
 
 impl PatchEmbed {
     pub fn new(patch_size: usize, embed_dim: usize) -> Self {
+        Self::new_with_rng(patch_size, embed_dim, &mut rand::thread_rng())
+    }
+
+    // 与 MAE 相同, 把卷积核视为展开后的线性层做 Xavier 初始化, 偏置为 0
+    pub fn new_with_rng<R: Rng + ?Sized>(patch_size: usize, embed_dim: usize, rng: &mut R) -> Self {
         let patch_dim = patch_size * patch_size * 3;
-        // let weight = Array2::<f32>::zeros((embed_dim, patch_dim));
-        // let bias = None;
-        let weight = Array2::random((embed_dim, patch_dim), StandardNormal);
-        let bias   = Some(Array2::random((embed_dim, 1), StandardNormal));
+        let weight = Initializer::Xavier.init((embed_dim, patch_dim), patch_dim, embed_dim, rng);
+        let bias = Some(Array2::zeros((embed_dim, 1)));
         PatchEmbed { patch_size, embed_dim, weight, bias }
     }
 
+    pub fn parameters_mut(&mut self) -> Vec<&mut Array2<f32>> {
+        let mut params = vec![&mut self.weight];
+        params.extend(self.bias.as_mut());
+        params
+    }
+}
+
+impl<S: Data<Elem = f32> + Sync> PatchEmbed<S> {
     pub fn forward(&self, img: &Array3<f32>) -> Array2<f32> {
+        let input = self.extract_patches(img);
+        let mut output = input.dot(&self.weight.t());
+        if let Some(bias) = &self.bias {
+            output += &bias.t();
+        }
+        output
+    }
+
+    pub fn forward_train(&self, img: &Array3<f32>) -> (Array2<f32>, PatchEmbedCache) {
+        let patches = self.extract_patches(img);
+        let mut output = patches.dot(&self.weight.t());
+        if let Some(bias) = &self.bias {
+            output += &bias.t();
+        }
+        (output, PatchEmbedCache { image_dim: img.dim(), patches })
+    }
+
+    // 返回对输入图像 (3, H, W) 的梯度, 不被任何 patch 覆盖的边缘像素梯度为 0
+    pub fn backward(&self, cache: &PatchEmbedCache, grad_out: &Array2<f32>) -> (Array3<f32>, PatchEmbedGrads) {
+        let grads = PatchEmbedGrads {
+            weight: grad_out.t().dot(&cache.patches),
+            bias: self.bias.as_ref().map(|_| grad_out.sum_axis(Axis(0)).insert_axis(Axis(1))),
+        };
+
+        let d_patches = grad_out.dot(&self.weight);
+        let ps = self.patch_size;
+        let (c, h, w) = cache.image_dim;
+        let pw = w / ps;
+        let mut d_img = Array3::<f32>::zeros((c, h, w));
+        for (idx, row) in d_patches.outer_iter().enumerate() {
+            let (i, j) = (idx / pw, idx % pw);
+            let patch = row.into_shape((c, ps, ps)).unwrap();
+            d_img
+                .slice_mut(s![.., i * ps..(i + 1) * ps, j * ps..(j + 1) * ps])
+                .assign(&patch);
+        }
+        (d_img, grads)
+    }
+
+    // (3, H, W) -> (num_patches, patch_dim), 每行是一个 (3, patch, patch) 块按行优先展开
+    fn extract_patches(&self, img: &Array3<f32>) -> Array2<f32> {
         let (_, h, w) = (img.shape()[0], img.shape()[1], img.shape()[2]);
         let ph = h / self.patch_size;
         let pw = w / self.patch_size;
@@ -43,30 +96,38 @@ impl PatchEmbed {
             })
             .collect();
 
-        let input = Array2::from_shape_vec((ph * pw, patch_dim), patches).unwrap();
-        let mut output = input.dot(&self.weight.t());
-        if let Some(bias) = &self.bias {
-            output += &bias.t();
-        }
+        Array2::from_shape_vec((ph * pw, patch_dim), patches).unwrap()
+    }
+
+    pub fn forward_captured(&self, img: &Array3<f32>, name: &str, capture: &mut ActivationCapture) -> Array2<f32> {
+        let output = self.forward(img);
+        capture.record(name, &output);
         output
     }
 }
 
-/// SIMD 加速 flatten patch (向量复制)
-fn flatten_patch_simd(input: &[f32]) -> Vec<f32> {
-    const LANES: usize = 8;
-    type SimdType = Simd<f32, LANES>;
+pub struct PatchEmbedCache {
+    image_dim: (usize, usize, usize),
+    patches: Array2<f32>,
+}
 
-    let mut output = Vec::with_capacity(input.len());
-    let chunks = input.chunks_exact(LANES);
-    let remainder = chunks.remainder();
+pub struct PatchEmbedGrads {
+    pub weight: Array2<f32>,
+    pub bias: Option<Array2<f32>>,
+}
 
-    for chunk in chunks {
-        let vec = SimdType::from_slice(chunk);
-        output.extend_from_slice(&vec.to_array());
+impl PatchEmbedGrads {
+    // 与 PatchEmbed::parameters_mut 顺序一致
+    pub fn tensors(&self) -> Vec<&Array2<f32>> {
+        let mut tensors = vec![&self.weight];
+        tensors.extend(self.bias.as_ref());
+        tensors
     }
+}
 
-    // 剩余数据
-    output.extend_from_slice(remainder);
+/// SIMD 加速 flatten patch (向量复制), 后端由 simd::backend() 在运行时选择
+fn flatten_patch_simd(input: &[f32]) -> Vec<f32> {
+    let mut output = vec![0.0; input.len()];
+    simd::copy(input, &mut output);
     output
 }
@@ -1,14 +1,29 @@
 use image::{DynamicImage, RgbImage, Rgb};
-use ndarray::Array3;
+use ndarray::{Array3, Array4, ArrayViewMut3, ArrayViewMut4, Zip};
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use crate::augment::{Augmentation, Geometry};
+use crate::encoder::EncoderConfig;
+use crate::resampler::DownsampleKind;
 
 const DEFAULT_MEAN: [f32; 3] = [0.48145466, 0.4578275, 0.40821073];
 const DEFAULT_STD: [f32; 3] = [0.26862954, 0.26130258, 0.27577711];
 
+#[derive(Clone)]
 pub struct ImageProcessor {
     pub image_size: u32,
     pub mean: [f32; 3],
     pub std: [f32; 3],
+    /// 训练时的数据增强, 在缩放之后、归一化之前施加; 只作用于带下标的
+    /// `preprocess_item` 和批处理接口, `preprocess` 等单图接口不增强
+    pub augment: Option<Augmentation>,
+    /// 以下三项只用于计算 `ImageMetadata` 中的 patch 网格和 token 数, 应与编码器配置一致
+    pub patch_size: usize,
+    pub downsample: DownsampleKind,
+    pub num_queries: usize,
+    /// 是否在 `ImageMetadata::hash` 中记录像素哈希; 需要遍历整张解码后的图, 缺省关闭
+    pub hash_pixels: bool,
 }
 
 impl ImageProcessor {
@@ -17,43 +32,337 @@ impl ImageProcessor {
             image_size,
             mean: DEFAULT_MEAN,
             std: DEFAULT_STD,
+            augment: None,
+            patch_size: 16,
+            downsample: DownsampleKind::None,
+            num_queries: 64,
+            hash_pixels: false,
         }
     }
 
+    /// 尺寸、patch 和 token 压缩方式都取自编码器配置
+    pub fn for_encoder(config: &EncoderConfig) -> Self {
+        Self {
+            patch_size: config.patch_size,
+            downsample: config.downsample,
+            num_queries: config.num_queries,
+            ..Self::new(config.image_size)
+        }
+    }
+
+    pub fn with_augmentation(mut self, augment: Augmentation) -> Self {
+        self.augment = Some(augment);
+        self
+    }
+
+    /// 缩放并归一化, 不施加增强
     pub fn preprocess(&self, img: &DynamicImage) -> Array3<f32> {
+        self.normalize(&resize_bicubic(&img.to_rgb8(), self.image_size, self.image_size))
+    }
+
+    /// 批内第 index 张图; 增强的随机数由 (augment.seed, index) 决定
+    pub fn preprocess_item(&self, img: &DynamicImage, index: u64) -> Array3<f32> {
+        let size = self.image_size as usize;
+        let mut out = Array3::zeros((3, size, size));
+        self.preprocess_item_into(img, index, out.view_mut());
+        out
+    }
+
+    /// 与 `preprocess_item` 相同, 结果写入已分配的 (3, S, S); 返回的 metadata 包含增强选中的裁剪和翻转
+    pub fn preprocess_item_into(&self, img: &DynamicImage, index: u64, mut out: ArrayViewMut3<f32>) -> ImageMetadata {
+        let mut meta = self.metadata(img);
         let resized = resize_bicubic(&img.to_rgb8(), self.image_size, self.image_size);
-        let mut arr = Array3::<f32>::zeros((3, self.image_size as usize, self.image_size as usize));
-        for (x, y, pixel) in resized.enumerate_pixels() {
+        let Some(augment) = &self.augment else {
+            self.normalize_into(&resized, out);
+            return meta;
+        };
+
+        let (w, h) = resized.dimensions();
+        let mut arr = Array3::from_shape_fn((3, h as usize, w as usize), |(c, y, x)| {
+            resized.get_pixel(x as u32, y as u32)[c] as f32 / 255.0
+        });
+        let geometry = augment.apply(&mut arr, index);
+        for (c, (mut dst, src)) in out.outer_iter_mut().zip(arr.outer_iter()).enumerate() {
+            Zip::from(&mut dst).and(&src).for_each(|d, &v| *d = (v - self.mean[c]) / self.std[c]);
+        }
+        meta.augmented = !augment.transforms.is_empty();
+        meta.apply_geometry(&geometry);
+        meta
+    }
+
+    /// 不缩放成正方形, 按 `NativeResolution::target_size` 缩放后归一化; 输出 (3, H, W) 的 patch 网格为
+    /// (H / patch_size, W / patch_size), 交给 `VisionEncoder::forward_native`
+    pub fn preprocess_native(&self, img: &DynamicImage, native: &NativeResolution) -> Array3<f32> {
+        self.check_factor(native);
+        let rgb = img.to_rgb8();
+        let (w, h) = native.target_size(rgb.width(), rgb.height());
+        self.normalize(&resize_bicubic(&rgb, w, h))
+    }
+
+    /// `preprocess` 对 img 所做处理的描述 (不含增强, 增强后的见 `preprocess_item_into` 的返回值)
+    pub fn metadata(&self, img: &DynamicImage) -> ImageMetadata {
+        self.describe(img, (self.image_size, self.image_size), ResizeStrategy::Stretch)
+    }
+
+    /// `preprocess_native` 对 img 所做处理的描述
+    pub fn native_metadata(&self, img: &DynamicImage, native: &NativeResolution) -> ImageMetadata {
+        self.check_factor(native);
+        let size = native.target_size(img.width(), img.height());
+        self.describe(img, size, ResizeStrategy::Native)
+    }
+
+    // 输出尺寸为 factor 的倍数, factor 不是 patch_size 的倍数时 forward_native 无法切分 patch
+    fn check_factor(&self, native: &NativeResolution) {
+        assert!(
+            native.factor > 0 && (native.factor as usize).is_multiple_of(self.patch_size),
+            "NativeResolution::factor {} must be a positive multiple of patch_size {}",
+            native.factor,
+            self.patch_size
+        );
+    }
+
+    fn describe(&self, img: &DynamicImage, size: (u32, u32), resize: ResizeStrategy) -> ImageMetadata {
+        let (w, h) = (img.width(), img.height());
+        let patch_grid = (size.1 as usize / self.patch_size, size.0 as usize / self.patch_size);
+        ImageMetadata {
+            original_size: (w, h),
+            processed_size: size,
+            scale: (size.0 as f32 / w as f32, size.1 as f32 / h as f32),
+            crop: (0.0, 0.0, w as f32, h as f32),
+            pad: (0, 0, 0, 0),
+            flipped: false,
+            resize,
+            augmented: false,
+            patch_grid,
+            num_tokens: self.downsample.num_tokens(patch_grid, self.num_queries),
+            source: None,
+            hash: self.hash_pixels.then(|| pixel_hash(img)),
+        }
+    }
+
+    // RGB8 -> 归一化的 (3, H, W)
+    pub(crate) fn normalize(&self, img: &RgbImage) -> Array3<f32> {
+        let (w, h) = img.dimensions();
+        let mut arr = Array3::<f32>::zeros((3, h as usize, w as usize));
+        self.normalize_into(img, arr.view_mut());
+        arr
+    }
+
+    fn normalize_into(&self, img: &RgbImage, mut out: ArrayViewMut3<f32>) {
+        for (x, y, pixel) in img.enumerate_pixels() {
             for c in 0..3 {
                 let val = pixel[c] as f32 / 255.0;
-                arr[[c, y as usize, x as usize]] = (val - self.mean[c]) / self.std[c];
+                out[[c, y as usize, x as usize]] = (val - self.mean[c]) / self.std[c];
             }
         }
-        arr
     }
 }
 
+/// 原生分辨率模式的尺寸约束
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NativeResolution {
+    /// 输出宽高都取它的整数倍, 一般为 patch_size (2x2 下采样时为 2 * patch_size)
+    pub factor: u32,
+    pub min_pixels: u32,
+    pub max_pixels: u32,
+}
+
+impl Default for NativeResolution {
+    fn default() -> Self {
+        NativeResolution { factor: 16, min_pixels: 112 * 112, max_pixels: 1024 * 1024 }
+    }
+}
+
+impl NativeResolution {
+    /// factor 取编码器的 patch_size, 2x2 下采样 (AvgPool / Conv) 时为 2 * patch_size
+    pub fn for_encoder(config: &EncoderConfig) -> Self {
+        let pool = match config.downsample {
+            DownsampleKind::AvgPool | DownsampleKind::Conv => 2,
+            DownsampleKind::None | DownsampleKind::Resampler => 1,
+        };
+        NativeResolution { factor: (config.patch_size * pool) as u32, ..Self::default() }
+    }
+
+    /// 保持宽高比缩放到 factor 的倍数, 且像素数落在 [min_pixels, max_pixels] 内 (同 Qwen2-VL 的 smart_resize);
+    /// 宽高比极端时短边至少为 factor, 此时再缩短长边以不超过 max_pixels。返回 (宽, 高)
+    pub fn target_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let f = self.factor as f64;
+        let (w, h) = (width as f64, height as f64);
+        let round = |v: f64| (v / f).round().max(1.0) * f;
+        let (mut out_w, mut out_h) = (round(w), round(h));
+        if out_w * out_h > self.max_pixels as f64 {
+            let beta = (w * h / self.max_pixels as f64).sqrt();
+            out_w = ((w / beta / f).floor().max(1.0)) * f;
+            out_h = ((h / beta / f).floor().max(1.0)) * f;
+        } else if out_w * out_h < self.min_pixels as f64 {
+            let beta = (self.min_pixels as f64 / (w * h)).sqrt();
+            out_w = (w * beta / f).ceil() * f;
+            out_h = (h * beta / f).ceil() * f;
+        }
+        // 短边被钳到 factor 后像素数可能仍超出预算
+        let max = self.max_pixels as f64;
+        if out_w * out_h > max {
+            if out_w >= out_h {
+                out_w = (max / out_h / f).floor().max(1.0) * f;
+            } else {
+                out_h = (max / out_w / f).floor().max(1.0) * f;
+            }
+        }
+        (out_w as u32, out_h as u32)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeStrategy {
+    /// 不保持宽高比, 直接缩放到 image_size x image_size
+    Stretch,
+    /// 保持宽高比缩放到 patch 的整数倍 (`NativeResolution`)
+    Native,
+}
+
+/// 一张图的预处理信息: 把输出上的坐标映射回原图 (grounding), 以及作为缓存键
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImageMetadata {
+    /// 原图 (宽, 高)
+    pub original_size: (u32, u32),
+    /// 输出张量的 (宽, 高)
+    pub processed_size: (u32, u32),
+    /// 裁剪区域缩放到输出的 (x, y) 比例
+    pub scale: (f32, f32),
+    /// 参与缩放的原图区域 (x, y, 宽, 高), 以原图像素为单位; 不裁剪时为整图,
+    /// 随机裁剪后一般不是整数, 平移增强后可以超出原图
+    pub crop: (f32, f32, f32, f32),
+    /// 缩放后四周的填充 (左, 上, 右, 下)
+    pub pad: (u32, u32, u32, u32),
+    /// 裁剪区域是否左右翻转
+    pub flipped: bool,
+    pub resize: ResizeStrategy,
+    /// 是否施加了随机增强; 其中的几何变换已计入 crop / scale / flipped
+    pub augmented: bool,
+    /// patch 网格 (行, 列)
+    pub patch_grid: (usize, usize),
+    /// 压缩后送入语言模型的视觉 token 数
+    pub num_tokens: usize,
+    pub source: Option<PathBuf>,
+    /// 解码后像素 (连同尺寸和颜色类型) 的 FNV-1a 哈希, 与文件格式和路径无关;
+    /// 仅在 `ImageProcessor::hash_pixels` 打开时计算
+    pub hash: Option<u64>,
+}
+
+impl ImageMetadata {
+    /// 输出张量上的点 (x, y) (连续坐标, 像素 i 的中心为 i + 0.5) 对应的原图坐标
+    pub fn to_original(&self, x: f32, y: f32) -> (f32, f32) {
+        let (cx, cy, _, _) = self.crop;
+        let (left, top, right, _) = self.pad;
+        let mut x = x - left as f32;
+        if self.flipped {
+            x = (self.processed_size.0 - left - right) as f32 - x;
+        }
+        (cx + x / self.scale.0, cy + (y - top as f32) / self.scale.1)
+    }
+
+    // 增强在 (无填充的) 输出画面上的几何变换换算到原图
+    fn apply_geometry(&mut self, geometry: &Geometry) {
+        let (wx, wy, ww, wh) = geometry.window;
+        let (cx, cy, _, _) = self.crop;
+        let (sx, sy) = self.scale;
+        self.crop = (cx + wx / sx, cy + wy / sy, ww / sx, wh / sy);
+        self.scale = (self.processed_size.0 as f32 / self.crop.2, self.processed_size.1 as f32 / self.crop.3);
+        self.flipped ^= geometry.flipped;
+    }
+}
+
+// 64 位 FNV-1a, 结果不随平台和编译器版本变化
+fn pixel_hash(img: &DynamicImage) -> u64 {
+    let header = [img.width().to_le_bytes(), img.height().to_le_bytes(), (img.color() as u32).to_le_bytes()];
+    header
+        .iter()
+        .flatten()
+        .chain(img.as_bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |h, &b| (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
 pub struct ImageBatchOutput {
-    pub image: Vec<Array3<f32>>,
+    /// (N, 3, S, S), 行优先连续存储, `as_slice` 即可零拷贝交给 ONNX 等运行时
+    pub image: Array4<f32>,
     pub input_ids: Vec<i64>,
     pub attention_mask: Vec<i64>,
+    /// 与 image 的第 0 维一一对应
+    pub metadata: Vec<ImageMetadata>,
 }
 
 pub fn process_images_in_batch(
     images: Vec<DynamicImage>,
     processor: &ImageProcessor,
 ) -> ImageBatchOutput {
-    let processed_images: Vec<_> = images
-        .par_iter()
-        .map(|img| processor.preprocess(img))
-        .collect();
+    let indices: Vec<u64> = (0..images.len() as u64).collect();
+    process_indexed_in_batch(&images, &indices, processor)
+}
+
+// 第 i 张图的增强下标为 indices[i] (流式处理时为在整个流中的下标)
+pub(crate) fn process_indexed_in_batch(
+    images: &[DynamicImage],
+    indices: &[u64],
+    processor: &ImageProcessor,
+) -> ImageBatchOutput {
+    let size = processor.image_size as usize;
+    let batch_size = images.len();
+    let mut image = Array4::zeros((batch_size, 3, size, size));
+    let metadata = process_indexed_into(images, indices, processor, image.view_mut());
 
-    let batch_size = processed_images.len();
     ImageBatchOutput {
-        image: processed_images,
+        image,
         input_ids: vec![0; batch_size],
         attention_mask: vec![1; batch_size],
+        metadata,
+    }
+}
+
+/// 把第 i 张图的结果直接写入 out 的第 i 个 (3, S, S), 返回各图的 metadata。
+/// out 可以是调用方的缓冲区 (锁页内存、与其他运行时共享的内存等),
+/// 用 `ArrayViewMut4::from_shape` 或 `from_shape_ptr` 包装即可, 不会再分配或拷贝整批数据
+pub fn process_images_into(
+    images: &[DynamicImage],
+    processor: &ImageProcessor,
+    out: ArrayViewMut4<f32>,
+) -> Vec<ImageMetadata> {
+    let indices: Vec<u64> = (0..images.len() as u64).collect();
+    process_indexed_into(images, &indices, processor, out)
+}
+
+fn process_indexed_into(
+    images: &[DynamicImage],
+    indices: &[u64],
+    processor: &ImageProcessor,
+    mut out: ArrayViewMut4<f32>,
+) -> Vec<ImageMetadata> {
+    let size = processor.image_size as usize;
+    assert_eq!(
+        out.dim(),
+        (images.len(), 3, size, size),
+        "output buffer must be (num_images, 3, image_size, image_size)"
+    );
+    let slots: Vec<_> = out.outer_iter_mut().collect();
+    images
+        .par_iter()
+        .zip(indices)
+        .zip(slots)
+        .map(|((img, &i), slot)| processor.preprocess_item_into(img, i, slot))
+        .collect()
+}
+
+/// 从文件读取并预处理, metadata 中记录来源路径; 任一文件读取失败时返回该错误
+pub fn process_image_files_in_batch<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    processor: &ImageProcessor,
+) -> image::ImageResult<ImageBatchOutput> {
+    let images = paths.par_iter().map(image::open).collect::<image::ImageResult<Vec<_>>>()?;
+    let mut output = process_images_in_batch(images, processor);
+    for (meta, path) in output.metadata.iter_mut().zip(paths) {
+        meta.source = Some(path.as_ref().to_path_buf());
     }
+    Ok(output)
 }
 
 fn cubic_kernel(x: f32) -> f32 {
@@ -67,17 +376,22 @@ fn cubic_kernel(x: f32) -> f32 {
     }
 }
 
-
 fn resize_bicubic(input: &RgbImage, out_w: u32, out_h: u32) -> RgbImage {
+    resize_bicubic_window(input, out_w, out_h, (0, 0, out_w, out_h))
+}
+
+// 只计算缩放到 out_w x out_h 后 window = (x0, y0, w, h) 内的像素, 结果与整图缩放后再裁剪相同
+pub(crate) fn resize_bicubic_window(input: &RgbImage, out_w: u32, out_h: u32, window: (u32, u32, u32, u32)) -> RgbImage {
     let (in_w, in_h) = input.dimensions();
-    let mut out = RgbImage::new(out_w, out_h);
+    let (x0, y0, win_w, win_h) = window;
+    let mut out = RgbImage::new(win_w, win_h);
 
-    for y in 0..out_h {
+    for y in y0..y0 + win_h {
         let fy = (y as f32 + 0.5) * (in_h as f32 / out_h as f32) - 0.5;
         let y_int = fy.floor() as i32;
         let y_frac = fy - y_int as f32;
 
-        for x in 0..out_w {
+        for x in x0..x0 + win_w {
             let fx = (x as f32 + 0.5) * (in_w as f32 / out_w as f32) - 0.5;
             let x_int = fx.floor() as i32;
             let x_frac = fx - x_int as f32;
@@ -110,7 +424,7 @@ fn resize_bicubic(input: &RgbImage, out_w: u32, out_h: u32) -> RgbImage {
                 rgb[1].clamp(0.0, 255.0) as u8,
                 rgb[2].clamp(0.0, 255.0) as u8,
             ]);
-            out.put_pixel(x, y, pixel);
+            out.put_pixel(x - x0, y - y0, pixel);
         }
     }
 
@@ -0,0 +1,194 @@
+// src/resampler.rs
+//
+// 编码器输出与 GLUProjection 之间的视觉 token 压缩:
+//   TokenResampler   可学习 query + 交叉注意力, 输出固定 num_queries 个 token (Perceiver / Q-Former)
+//   avg_pool_2x2     patch 网格上 2x2 平均池化, token 数约为 1/4
+//   ConvDownsampler  2x2 stride 2 卷积 (CogVLM2), token 数约为 1/4
+// 网格边长为奇数时最后一行/列单独成窗: 池化只平均存在的 token, 卷积按 0 填充
+
+use ndarray::{Array2, ArrayBase, Axis, Data, Ix2, OwnedRepr};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::init::Initializer;
+use crate::transformer::{FeedForward, LayerNorm, MultiHeadAttention};
+
+/// 配置中的压缩方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownsampleKind {
+    #[default]
+    None,
+    Resampler,
+    AvgPool,
+    Conv,
+}
+
+impl DownsampleKind {
+    /// grid 网格压缩后的 token 数, 与对应 `Downsampler::num_tokens` 相同
+    pub fn num_tokens(self, grid: (usize, usize), num_queries: usize) -> usize {
+        match self {
+            DownsampleKind::None => grid.0 * grid.1,
+            DownsampleKind::Resampler => num_queries,
+            DownsampleKind::AvgPool | DownsampleKind::Conv => grid.0.div_ceil(2) * grid.1.div_ceil(2),
+        }
+    }
+}
+
+pub struct TokenResampler<S: Data<Elem = f32> = OwnedRepr<f32>> {
+    pub num_queries: usize,
+    pub dim: usize,
+    pub queries: ArrayBase<S, Ix2>, // (num_queries, dim)
+    pub ln_q: LayerNorm<S>,
+    pub ln_kv: LayerNorm<S>,
+    pub attn: MultiHeadAttention<S>,
+    pub ln_ffn: LayerNorm<S>,
+    pub ffn: FeedForward<S>,
+}
+
+impl TokenResampler {
+    pub fn new(dim: usize, num_queries: usize, num_heads: usize, ff_dim: usize) -> Self {
+        Self::new_with_rng(dim, num_queries, num_heads, ff_dim, &mut rand::thread_rng())
+    }
+
+    // query 按 Q-Former 的做法取 std = 0.02 的截断正态
+    pub fn new_with_rng<R: Rng + ?Sized>(
+        dim: usize,
+        num_queries: usize,
+        num_heads: usize,
+        ff_dim: usize,
+        rng: &mut R,
+    ) -> Self {
+        TokenResampler {
+            num_queries,
+            dim,
+            queries: Initializer::TruncatedNormal { std: 0.02 }.init((num_queries, dim), dim, dim, rng),
+            ln_q: LayerNorm::new(dim),
+            ln_kv: LayerNorm::new(dim),
+            attn: MultiHeadAttention::cross_with_rng(dim, dim, num_heads, num_heads, rng),
+            ln_ffn: LayerNorm::new(dim),
+            ffn: FeedForward::new_with_rng(dim, ff_dim, rng),
+        }
+    }
+}
+
+impl<S: Data<Elem = f32> + Sync> TokenResampler<S> {
+    // x (seq_len, dim) -> (num_queries, dim), 与 seq_len 无关
+    pub fn forward(&self, x: &Array2<f32>) -> Array2<f32> {
+        let q = self.ln_q.forward(&self.queries.to_owned());
+        let kv = self.ln_kv.forward(x);
+        let h = &self.queries + &self.attn.forward_cross(&q, &kv, None);
+        let ffn_out = self.ffn.forward(&self.ln_ffn.forward(&h));
+        h + ffn_out
+    }
+}
+
+pub struct ConvDownsampler<S: Data<Elem = f32> = OwnedRepr<f32>> {
+    pub dim: usize,
+    // 按窗口内 (0,0) (0,1) (1,0) (1,1) 顺序拼接的 4 个 token -> dim, 即 Conv2d(k=2, s=2) 的权重展开
+    pub weight: ArrayBase<S, Ix2>, // (4 * dim, dim)
+    pub bias: ArrayBase<S, Ix2>,   // (1, dim)
+}
+
+impl ConvDownsampler {
+    pub fn new(dim: usize) -> Self {
+        Self::new_with_rng(dim, &mut rand::thread_rng())
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(dim: usize, rng: &mut R) -> Self {
+        ConvDownsampler {
+            dim,
+            weight: Initializer::Xavier.linear(4 * dim, dim, rng),
+            bias: Array2::zeros((1, dim)),
+        }
+    }
+}
+
+impl<S: Data<Elem = f32> + Sync> ConvDownsampler<S> {
+    pub fn forward(&self, x: &Array2<f32>, grid: (usize, usize)) -> Array2<f32> {
+        let (gh, gw) = grid;
+        let (oh, ow) = (gh.div_ceil(2), gw.div_ceil(2));
+        let d = x.shape()[1];
+
+        let mut windows = Array2::<f32>::zeros((oh * ow, 4 * d));
+        for (idx, mut row) in windows.axis_iter_mut(Axis(0)).enumerate() {
+            let (i, j) = (idx / ow, idx % ow);
+            for (k, (di, dj)) in [(0, 0), (0, 1), (1, 0), (1, 1)].into_iter().enumerate() {
+                let (r, c) = (2 * i + di, 2 * j + dj);
+                if r < gh && c < gw {
+                    row.slice_mut(ndarray::s![k * d..(k + 1) * d]).assign(&x.row(r * gw + c));
+                }
+            }
+        }
+        windows.dot(&self.weight) + &self.bias
+    }
+}
+
+// 2x2 平均池化, x 为按行优先排列的 (gh * gw, dim) patch 网格
+pub fn avg_pool_2x2(x: &Array2<f32>, grid: (usize, usize)) -> Array2<f32> {
+    let (gh, gw) = grid;
+    assert_eq!(x.shape()[0], gh * gw, "token count must equal grid_h * grid_w");
+    let (oh, ow) = (gh.div_ceil(2), gw.div_ceil(2));
+
+    let mut out = Array2::<f32>::zeros((oh * ow, x.shape()[1]));
+    for (idx, mut row) in out.axis_iter_mut(Axis(0)).enumerate() {
+        let (i, j) = (idx / ow, idx % ow);
+        let rows = (2 * i)..(2 * i + 2).min(gh);
+        let cols = (2 * j)..(2 * j + 2).min(gw);
+        let count = (rows.len() * cols.len()) as f32;
+        for r in rows {
+            for c in cols.clone() {
+                row += &x.row(r * gw + c);
+            }
+        }
+        row /= count;
+    }
+    out
+}
+
+/// 编码器与 GLUProjection 之间的 token 压缩模块
+pub enum Downsampler<S: Data<Elem = f32> = OwnedRepr<f32>> {
+    None,
+    Resampler(Box<TokenResampler<S>>),
+    AvgPool,
+    Conv(ConvDownsampler<S>),
+}
+
+impl Downsampler {
+    pub fn new<R: Rng + ?Sized>(
+        kind: DownsampleKind,
+        dim: usize,
+        num_queries: usize,
+        num_heads: usize,
+        ff_dim: usize,
+        rng: &mut R,
+    ) -> Self {
+        match kind {
+            DownsampleKind::None => Downsampler::None,
+            DownsampleKind::Resampler => {
+                Downsampler::Resampler(Box::new(TokenResampler::new_with_rng(dim, num_queries, num_heads, ff_dim, rng)))
+            }
+            DownsampleKind::AvgPool => Downsampler::AvgPool,
+            DownsampleKind::Conv => Downsampler::Conv(ConvDownsampler::new_with_rng(dim, rng)),
+        }
+    }
+}
+
+impl<S: Data<Elem = f32> + Sync> Downsampler<S> {
+    pub fn forward(&self, x: &Array2<f32>, grid: (usize, usize)) -> Array2<f32> {
+        match self {
+            Downsampler::None => x.clone(),
+            Downsampler::Resampler(r) => r.forward(x),
+            Downsampler::AvgPool => avg_pool_2x2(x, grid),
+            Downsampler::Conv(c) => c.forward(x, grid),
+        }
+    }
+
+    /// grid 网格输入对应的输出 token 数
+    pub fn num_tokens(&self, grid: (usize, usize)) -> usize {
+        match self {
+            Downsampler::None => grid.0 * grid.1,
+            Downsampler::Resampler(r) => r.num_queries,
+            Downsampler::AvgPool | Downsampler::Conv(_) => grid.0.div_ceil(2) * grid.1.div_ceil(2),
+        }
+    }
+}
@@ -1,79 +1,16 @@
 use ndarray::{Array2, Axis, s};
 use rayon::prelude::*;
-use std::simd::{Simd};
+use crate::simd::{self, SimdBackend};
 
 // simd
+#[deprecated(note = "rebuilds the sin/cos tables on every call; build a `RopeCache` once and reuse `RopeCache::apply`, or set `MultiHeadAttention::rope` to rotate Q/K per head")]
 pub fn apply_rope_simd_parallel(tensor: &mut Array2<f32>, dim: usize) {
-    const LANES: usize = 8;
-    type Vf32 = Simd<f32, LANES>;
-
-    let seq_len = tensor.shape()[0];
-    let half_dim = dim / 2;
-
-    let theta: Vec<f32> = (0..half_dim)
-        .map(|i| 1.0 / 10000f32.powf((2 * i) as f32 / dim as f32))
-        .collect();
-
-    for pos in 0..seq_len {
-        let pos_f32 = pos as f32;
-        let mut row = tensor.slice_mut(s![pos, ..]);
-
-        let mut i = 0;
-        while i + LANES <= half_dim {
-            let angles: [f32; LANES] = {
-                let mut arr = [0.0; LANES];
-                for lane in 0..LANES {
-                    arr[lane] = pos_f32 * theta[i + lane];
-                }
-                arr
-            };
-
-            // 逐元素计算 sin_cos 收集结果到数组
-            // TODO:优化
-            let mut sin_arr = [0.0; LANES];
-            let mut cos_arr = [0.0; LANES];
-            for lane in 0..LANES {
-                let (s, c) = angles[lane].sin_cos();
-                sin_arr[lane] = s;
-                cos_arr[lane] = c;
-            }
-
-            let sin = Vf32::from_array(sin_arr);
-            let cos = Vf32::from_array(cos_arr);
-
-            let mut x0_vals = [0.0; LANES];
-            let mut x1_vals = [0.0; LANES];
-            for lane in 0..LANES {
-                x0_vals[lane] = row[2 * (i + lane)];
-                x1_vals[lane] = row[2 * (i + lane) + 1];
-            }
-
-            let x0 = Vf32::from_array(x0_vals);
-            let x1 = Vf32::from_array(x1_vals);
-
-            let new_x0 = cos * x0 - sin * x1;
-            let new_x1 = sin * x0 + cos * x1;
-
-            for lane in 0..LANES {
-                row[2 * (i + lane)] = new_x0[lane];
-                row[2 * (i + lane) + 1] = new_x1[lane];
-            }
-
-            i += LANES;
-        }
-
-        while i < half_dim {
-            let angle = pos_f32 * theta[i];
-            let (sin, cos) = angle.sin_cos();
-            let (x0, x1) = (row[2 * i], row[2 * i + 1]);
-            row[2 * i] = cos * x0 - sin * x1;
-            row[2 * i + 1] = sin * x0 + cos * x1;
-            i += 1;
-        }
-    }
+    let cache = RopeCache::new(tensor.shape()[0], dim, 10000.0);
+    cache.apply_simd(tensor);
 }
 
 // 原版
+#[deprecated(note = "rebuilds the sin/cos tables on every call; build a `RopeCache` once and reuse `RopeCache::apply`, or set `MultiHeadAttention::rope` to rotate Q/K per head")]
 pub fn apply_rope(tensor: &mut Array2<f32>, dim: usize) {
     let seq_len = tensor.shape()[0];
     let theta: Vec<f32> = (0..dim / 2)
@@ -95,22 +32,262 @@ pub fn apply_rope(tensor: &mut Array2<f32>, dim: usize) {
 }
 
 // rayon优化
+#[deprecated(note = "rebuilds the sin/cos tables on every call; build a `RopeCache` once and reuse `RopeCache::apply`, or set `MultiHeadAttention::rope` to rotate Q/K per head")]
 pub fn apply_rope_parallel(tensor: &mut Array2<f32>, dim: usize) {
-    let seq_len = tensor.shape()[0];
-    let theta: Vec<f32> = (0..dim / 2)
-        .map(|i| 1.0 / 10000f32.powf((2 * i) as f32 / dim as f32))
-        .collect();
+    let cache = RopeCache::new(tensor.shape()[0], dim, 10000.0);
+    cache.apply(tensor);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RopePairing {
+    /// 相邻元素成对 (2i, 2i+1), 即 apply_rope 的布局
+    Interleaved,
+    /// HF 风格 rotate-half, 成对 (i, i + rotary_dim/2)
+    HalfSplit,
+}
 
-    let rows: Vec<_> = tensor.axis_iter_mut(Axis(0)).collect();
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RopeScaling {
+    None,
+    /// 位置线性插值: pos / factor
+    Linear { factor: f32 },
+    /// NTK-aware: base * factor^(rotary_dim / (rotary_dim - 2))
+    Ntk { factor: f32 },
+}
 
-    rows.into_par_iter().enumerate().for_each(|(pos, mut row)| {
-        for i in 0..(dim / 2) {
-            let angle = pos as f32 * theta[i];
-            let (sin, cos) = angle.sin_cos();
+/// RoPE 参数。只旋转每行前 rotary_dim 列, 其余列保持不变。
+#[derive(Clone, Debug, PartialEq)]
+pub struct RopeConfig {
+    pub dim: usize,
+    pub rotary_dim: usize,
+    pub base: f32,
+    pub pairing: RopePairing,
+    pub scaling: RopeScaling,
+}
+
+impl RopeConfig {
+    pub fn new(dim: usize) -> Self {
+        RopeConfig {
+            dim,
+            rotary_dim: dim,
+            base: 10000.0,
+            pairing: RopePairing::Interleaved,
+            scaling: RopeScaling::None,
+        }
+    }
+
+    /// 考虑 NTK 缩放后的频率 theta_i, 共 rotary_dim / 2 个
+    pub fn inv_freq(&self) -> Vec<f32> {
+        let r = self.rotary_dim;
+        let base = match self.scaling {
+            RopeScaling::Ntk { factor } if r > 2 => self.base * factor.powf(r as f32 / (r - 2) as f32),
+            _ => self.base,
+        };
+        (0..r / 2).map(|i| 1.0 / base.powf((2 * i) as f32 / r as f32)).collect()
+    }
+
+    /// 考虑线性插值后的位置
+    pub fn position(&self, pos: usize) -> f32 {
+        match self.scaling {
+            RopeScaling::Linear { factor } => pos as f32 / factor,
+            _ => pos as f32,
+        }
+    }
+
+    // 第 i 对的两个列下标
+    fn pair(&self, i: usize) -> (usize, usize) {
+        match self.pairing {
+            RopePairing::Interleaved => (2 * i, 2 * i + 1),
+            RopePairing::HalfSplit => (i, i + self.rotary_dim / 2),
+        }
+    }
+}
 
-            let (x0, x1) = (row[2 * i], row[2 * i + 1]);
-            row[2 * i] = cos * x0 - sin * x1;
-            row[2 * i + 1] = sin * x0 + cos * x1;
+// 按 RopeConfig 逐元素计算的参考实现, 用于和 RopeCache 交叉验证
+pub fn apply_rope_with_config(tensor: &mut Array2<f32>, config: &RopeConfig) {
+    let theta = config.inv_freq();
+    for pos in 0..tensor.shape()[0] {
+        let p = config.position(pos);
+        for (i, &t) in theta.iter().enumerate() {
+            let (sin, cos) = (p * t).sin_cos();
+            let (a, b) = config.pair(i);
+            let (x0, x1) = (tensor[[pos, a]], tensor[[pos, b]]);
+            tensor[[pos, a]] = cos * x0 - sin * x1;
+            tensor[[pos, b]] = sin * x0 + cos * x1;
         }
-    });
-}
\ No newline at end of file
+    }
+}
+
+/// 预先计算的 sin/cos 表, 按 (max_seq, RopeConfig) 创建一次后重复使用。
+///
+/// 表按成对元素在行内的位置展开, 记第 i 对为 (a, b):
+/// `cos[pos, a] = cos[pos, b] = cos(pos * theta_i)`, `sin[pos, a] = -sin(..)`, `sin[pos, b] = sin(..)`。
+/// 旋转就是 `out[j] = x[j] * cos[j] + x[partner(j)] * sin[j]`; 交错布局的 partner 是 `j ^ 1`,
+/// rotate-half 布局是另一半的同一位置, 两种情况每行都可以直接整段载入 SIMD 向量。
+pub struct RopeCache {
+    pub max_seq: usize,
+    pub config: RopeConfig,
+    pub cos: Array2<f32>, // (max_seq, rotary_dim / 2 * 2)
+    pub sin: Array2<f32>,
+}
+
+impl RopeCache {
+    pub fn new(max_seq: usize, dim: usize, base: f32) -> Self {
+        RopeCache::from_config(max_seq, RopeConfig { base, ..RopeConfig::new(dim) })
+    }
+
+    pub fn from_config(max_seq: usize, config: RopeConfig) -> Self {
+        assert!(config.rotary_dim <= config.dim, "rotary_dim {} exceeds dim {}", config.rotary_dim, config.dim);
+        let theta = config.inv_freq();
+        Self::from_angles(max_seq, theta.len(), config.clone(), |pos, i| config.position(pos) * theta[i])
+    }
+
+    /// 2D 轴向 RoPE, 位置按行优先排列的 rows x cols 个 patch:
+    /// 前一半频率对按行号旋转, 后一半按列号旋转, 每个轴各用 rotary_dim / 2 维的频率表
+    pub fn from_grid(grid: (usize, usize), config: RopeConfig) -> Self {
+        assert!(config.rotary_dim <= config.dim, "rotary_dim {} exceeds dim {}", config.rotary_dim, config.dim);
+        assert!(
+            config.rotary_dim.is_multiple_of(4),
+            "2D rope needs rotary_dim divisible by 4, got {}",
+            config.rotary_dim
+        );
+        let (rows, cols) = grid;
+        let axis = RopeConfig { rotary_dim: config.rotary_dim / 2, ..config.clone() };
+        let theta = axis.inv_freq();
+        let quarter = theta.len();
+        Self::from_angles(rows * cols, 2 * quarter, config, |pos, i| {
+            if i < quarter {
+                axis.position(pos / cols) * theta[i]
+            } else {
+                axis.position(pos % cols) * theta[i - quarter]
+            }
+        })
+    }
+
+    /// 打包序列用的表: 依次取每个 (cache, len) 的前 len 行拼接, 使各段位置都从 0 开始;
+    /// 各 cache 的 RopeConfig 须相同
+    pub fn packed(parts: &[(&RopeCache, usize)]) -> Self {
+        let config = parts.first().expect("packed rope needs at least one part").0.config.clone();
+        for (cache, len) in parts {
+            assert_eq!(cache.config, config, "packed rope caches must share a RopeConfig");
+            assert!(*len <= cache.max_seq, "segment length {} exceeds RopeCache max_seq {}", len, cache.max_seq);
+        }
+        let cos: Vec<_> = parts.iter().map(|(c, len)| c.cos.slice(s![..*len, ..])).collect();
+        let sin: Vec<_> = parts.iter().map(|(c, len)| c.sin.slice(s![..*len, ..])).collect();
+        RopeCache {
+            max_seq: parts.iter().map(|(_, len)| len).sum(),
+            config,
+            cos: ndarray::concatenate(Axis(0), &cos).unwrap(),
+            sin: ndarray::concatenate(Axis(0), &sin).unwrap(),
+        }
+    }
+
+    // 按 angle(pos, 第 i 对) 填表
+    fn from_angles(max_seq: usize, num_pairs: usize, config: RopeConfig, angle: impl Fn(usize, usize) -> f32) -> Self {
+        let width = 2 * num_pairs;
+        let mut cos = Array2::<f32>::zeros((max_seq, width));
+        let mut sin = Array2::<f32>::zeros((max_seq, width));
+        for pos in 0..max_seq {
+            for i in 0..num_pairs {
+                let (s, c) = angle(pos, i).sin_cos();
+                let (a, b) = config.pair(i);
+                cos[[pos, a]] = c;
+                cos[[pos, b]] = c;
+                sin[[pos, a]] = -s;
+                sin[[pos, b]] = s;
+            }
+        }
+
+        RopeCache { max_seq, config, cos, sin }
+    }
+
+    /// 对 tensor 的前 rotary_dim 列做旋转, 按行并行
+    pub fn apply(&self, tensor: &mut Array2<f32>) {
+        self.apply_heads(tensor, 1);
+    }
+
+    pub fn apply_simd(&self, tensor: &mut Array2<f32>) {
+        self.apply_heads_simd(tensor, 1);
+    }
+
+    /// tensor 形状 (seq_len, num_heads * dim), 每个 head 的前 rotary_dim 列分别旋转
+    pub fn apply_heads(&self, tensor: &mut Array2<f32>, num_heads: usize) {
+        match self.config.pairing {
+            RopePairing::Interleaved => self.apply_with(tensor, num_heads, &self.sin, rotate_row),
+            RopePairing::HalfSplit => self.apply_with(tensor, num_heads, &self.sin, rotate_half_row),
+        }
+    }
+
+    pub fn apply_heads_simd(&self, tensor: &mut Array2<f32>, num_heads: usize) {
+        match self.config.pairing {
+            RopePairing::Interleaved => self.apply_with(tensor, num_heads, &self.sin, rotate_row_simd),
+            RopePairing::HalfSplit => self.apply_with(tensor, num_heads, &self.sin, rotate_half_row_simd),
+        }
+    }
+
+    /// 反向旋转 (旋转矩阵的转置), 用于把梯度传回旋转前的 Q/K
+    pub fn apply_heads_transposed(&self, tensor: &mut Array2<f32>, num_heads: usize) {
+        let neg_sin = self.sin.mapv(|v| -v);
+        match self.config.pairing {
+            RopePairing::Interleaved => self.apply_with(tensor, num_heads, &neg_sin, rotate_row_simd),
+            RopePairing::HalfSplit => self.apply_with(tensor, num_heads, &neg_sin, rotate_half_row_simd),
+        }
+    }
+
+    fn apply_with(
+        &self,
+        tensor: &mut Array2<f32>,
+        num_heads: usize,
+        sin_table: &Array2<f32>,
+        kernel: fn(&mut [f32], &[f32], &[f32]),
+    ) {
+        let seq_len = tensor.shape()[0];
+        let dim = self.config.dim;
+        assert!(seq_len <= self.max_seq, "sequence length {} exceeds RopeCache max_seq {}", seq_len, self.max_seq);
+        assert!(
+            tensor.shape()[1] >= num_heads * dim,
+            "tensor width {} is smaller than {} heads of rope dim {}",
+            tensor.shape()[1],
+            num_heads,
+            dim
+        );
+        let width = self.cos.shape()[1];
+
+        let rows: Vec<_> = tensor.axis_iter_mut(Axis(0)).collect();
+        rows.into_par_iter().enumerate().for_each(|(pos, mut row)| {
+            let cos = self.cos.row(pos);
+            let sin = sin_table.row(pos);
+            let (cos, sin) = (cos.as_slice().unwrap(), sin.as_slice().unwrap());
+            for h in 0..num_heads {
+                let start = h * dim;
+                match row.as_slice_mut() {
+                    Some(slice) => kernel(&mut slice[start..start + width], cos, sin),
+                    None => {
+                        let mut buf = row.slice(s![start..start + width]).to_vec();
+                        kernel(&mut buf, cos, sin);
+                        row.slice_mut(s![start..start + width]).assign(&ndarray::ArrayView1::from(&buf));
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn rotate_row(row: &mut [f32], cos: &[f32], sin: &[f32]) {
+    simd::rope_interleaved_with(SimdBackend::Scalar, row, cos, sin);
+}
+
+fn rotate_row_simd(row: &mut [f32], cos: &[f32], sin: &[f32]) {
+    simd::rope_interleaved(row, cos, sin);
+}
+
+fn rotate_half_row(row: &mut [f32], cos: &[f32], sin: &[f32]) {
+    let (lo, hi) = row.split_at_mut(cos.len() / 2);
+    simd::rope_half_with(SimdBackend::Scalar, lo, hi, cos, sin);
+}
+
+// rotate-half 的两半各自连续, 不需要 swizzle
+fn rotate_half_row_simd(row: &mut [f32], cos: &[f32], sin: &[f32]) {
+    let (lo, hi) = row.split_at_mut(cos.len() / 2);
+    simd::rope_half(lo, hi, cos, sin);
+}
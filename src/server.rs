@@ -0,0 +1,286 @@
+// src/server.rs
+//
+// 本地 HTTP 编码服务 (feature = "server")
+//
+//   POST /embed       -> {"shape": [T, D], "embedding": [[...]]}
+//   POST /preprocess  -> {"shape": [3, H, W], "tensor": [[[...]]]}
+//
+// 请求体为 multipart/form-data (取第一个文件字段) 或 JSON {"image": "<base64>"}。
+// 编码请求进入 BatchQueue, 凑满 max_batch_size 或等待 batch_timeout 后一起调用 forward_batch。
+// 请求体超过 max_body_bytes 时返回 413; 某一批编码 panic 时该批请求返回 500, 队列继续服务。
+
+use base64::Engine;
+use ndarray::{Array2, Array3, Data};
+use std::error::Error;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response};
+use crate::encoder::VisionEncoder;
+use crate::processor::ImageProcessor;
+
+pub struct ServerConfig {
+    pub addr: String,
+    pub max_batch_size: usize,
+    pub batch_timeout: Duration,
+    pub workers: usize,
+    /// 请求体的最大字节数
+    pub max_body_bytes: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            addr: "127.0.0.1:8080".to_string(),
+            max_batch_size: 8,
+            batch_timeout: Duration::from_millis(10),
+            workers: 4,
+            max_body_bytes: 32 << 20,
+        }
+    }
+}
+
+struct Job {
+    image: Array3<f32>,
+    reply: Sender<Array2<f32>>,
+}
+
+/// 动态批处理队列: 后台线程收集并发请求, 批量调用编码器
+pub struct BatchQueue {
+    sender: Sender<Job>,
+    batches: Arc<AtomicUsize>,
+}
+
+impl BatchQueue {
+    pub fn new<S>(encoder: Arc<VisionEncoder<S>>, max_batch_size: usize, batch_timeout: Duration) -> Self
+    where
+        S: Data<Elem = f32> + Send + Sync + 'static,
+    {
+        let (sender, receiver) = channel();
+        let batches = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&batches);
+        thread::spawn(move || run_batches(encoder, receiver, max_batch_size.max(1), batch_timeout, counter));
+        BatchQueue { sender, batches }
+    }
+
+    /// 提交一张预处理后的图片并阻塞等待结果; 所在批次编码失败时返回 None
+    pub fn encode(&self, image: Array3<f32>) -> Option<Array2<f32>> {
+        let (reply, result) = channel();
+        self.sender.send(Job { image, reply }).ok()?;
+        result.recv().ok()
+    }
+
+    /// 已执行的批次数
+    pub fn batches_run(&self) -> usize {
+        self.batches.load(Ordering::Relaxed)
+    }
+}
+
+fn run_batches<S: Data<Elem = f32> + Sync>(
+    encoder: Arc<VisionEncoder<S>>,
+    receiver: Receiver<Job>,
+    max_batch_size: usize,
+    batch_timeout: Duration,
+    batches: Arc<AtomicUsize>,
+) {
+    // 所有 sender 释放后 recv 返回 Err, 线程退出
+    while let Ok(first) = receiver.recv() {
+        let deadline = Instant::now() + batch_timeout;
+        let mut jobs = vec![first];
+        while jobs.len() < max_batch_size {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match receiver.recv_timeout(remaining) {
+                Ok(job) => jobs.push(job),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        let (images, replies): (Vec<_>, Vec<_>) = jobs.into_iter().map(|j| (j.image, j.reply)).unzip();
+        // panic 只影响这一批: 丢弃 replies 让等待方收到 None, 线程继续处理后续请求
+        let outputs = panic::catch_unwind(AssertUnwindSafe(|| encoder.forward_batch(&images)));
+        batches.fetch_add(1, Ordering::Relaxed);
+        match outputs {
+            Ok(outputs) => {
+                for (reply, out) in replies.into_iter().zip(outputs) {
+                    let _ = reply.send(out);
+                }
+            }
+            Err(_) => eprintln!("server: encoder panicked on a batch of {}", replies.len()),
+        }
+    }
+}
+
+pub struct EmbeddingServer {
+    http: Arc<tiny_http::Server>,
+    queue: Arc<BatchQueue>,
+    processor: Arc<ImageProcessor>,
+    workers: usize,
+    max_body_bytes: usize,
+    stopped: Arc<AtomicBool>,
+}
+
+impl EmbeddingServer {
+    /// 权重可以是自有的, 也可以借用 'static 的内存映射 (`mmap::MappedWeights`)
+    pub fn bind<S>(encoder: VisionEncoder<S>, config: ServerConfig) -> Result<Self, Box<dyn Error + Send + Sync>>
+    where
+        S: Data<Elem = f32> + Send + Sync + 'static,
+    {
+        let http = tiny_http::Server::http(config.addr.as_str())?;
+        let processor = ImageProcessor::for_encoder(&encoder.config);
+        let queue = BatchQueue::new(Arc::new(encoder), config.max_batch_size, config.batch_timeout);
+        Ok(EmbeddingServer {
+            http: Arc::new(http),
+            queue: Arc::new(queue),
+            processor: Arc::new(processor),
+            workers: config.workers.max(1),
+            max_body_bytes: config.max_body_bytes,
+            stopped: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    pub fn queue(&self) -> &Arc<BatchQueue> {
+        &self.queue
+    }
+
+    /// 让所有 worker 的 recv 返回, run 随之结束
+    pub fn shutdown(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        for _ in 0..self.workers {
+            self.http.unblock();
+        }
+    }
+
+    /// 阻塞运行, 直到 shutdown
+    pub fn run(&self) {
+        let handles: Vec<_> = (0..self.workers)
+            .map(|_| {
+                let http = Arc::clone(&self.http);
+                let queue = Arc::clone(&self.queue);
+                let processor = Arc::clone(&self.processor);
+                let stopped = Arc::clone(&self.stopped);
+                let max_body_bytes = self.max_body_bytes;
+                thread::spawn(move || loop {
+                    match http.recv() {
+                        Ok(request) => handle(request, &queue, &processor, max_body_bytes),
+                        Err(_) if stopped.load(Ordering::SeqCst) => break,
+                        Err(e) => eprintln!("server: failed to accept request: {}", e),
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            let _ = h.join();
+        }
+    }
+}
+
+fn handle(mut request: Request, queue: &BatchQueue, processor: &ImageProcessor, max_body_bytes: usize) {
+    let response = if *request.method() != Method::Post {
+        Err((405, "only POST is supported".to_string()))
+    } else {
+        let route = request.url().split('?').next().unwrap_or("").to_string();
+        match route.as_str() {
+            "/embed" | "/preprocess" => decode_image(&mut request, max_body_bytes).and_then(|img| {
+                let tensor = processor.preprocess(&img);
+                if route == "/preprocess" {
+                    let rows: Vec<Vec<Vec<f32>>> = tensor
+                        .outer_iter()
+                        .map(|c| c.outer_iter().map(|r| r.to_vec()).collect())
+                        .collect();
+                    Ok(serde_json::json!({ "shape": tensor.shape(), "tensor": rows }))
+                } else {
+                    let out = queue.encode(tensor).ok_or((500, "encoding failed".to_string()))?;
+                    let rows: Vec<Vec<f32>> = out.outer_iter().map(|r| r.to_vec()).collect();
+                    Ok(serde_json::json!({ "shape": out.shape(), "embedding": rows }))
+                }
+            }),
+            _ => Err((404, format!("unknown route {}", route))),
+        }
+    };
+
+    let (status, body) = match response {
+        Ok(body) => (200, body),
+        Err((status, msg)) => (status, serde_json::json!({ "error": msg })),
+    };
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let _ = request.respond(Response::from_string(body.to_string()).with_status_code(status).with_header(header));
+}
+
+fn decode_image(request: &mut Request, max_body_bytes: usize) -> Result<image::DynamicImage, (u16, String)> {
+    let content_type = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Content-Type"))
+        .map(|h| h.value.as_str().to_string())
+        .unwrap_or_default();
+
+    let too_large = || (413, format!("request body exceeds {} bytes", max_body_bytes));
+    if request.body_length().is_some_and(|len| len > max_body_bytes) {
+        return Err(too_large());
+    }
+    // 没有 Content-Length (chunked) 时按读到的字节数判断
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(max_body_bytes as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| (400, format!("failed to read body: {}", e)))?;
+    if body.len() > max_body_bytes {
+        return Err(too_large());
+    }
+
+    let bytes = if content_type.starts_with("multipart/form-data") {
+        let boundary = content_type
+            .split(';')
+            .find_map(|p| p.trim().strip_prefix("boundary="))
+            .map(|b| b.trim_matches('"').to_string())
+            .ok_or((400, "missing multipart boundary".to_string()))?;
+        multipart_file(&body, &boundary).ok_or((400, "no file part in multipart body".to_string()))?
+    } else {
+        let json: serde_json::Value =
+            serde_json::from_slice(&body).map_err(|e| (400, format!("invalid JSON: {}", e)))?;
+        let encoded = json["image"].as_str().ok_or((400, "missing \"image\" field".to_string()))?;
+        // 兼容 data:image/png;base64,... 形式
+        let encoded = encoded.split_once(";base64,").map_or(encoded, |(_, data)| data);
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| (400, format!("invalid base64: {}", e)))?
+    };
+
+    image::load_from_memory(&bytes).map_err(|e| (400, format!("failed to decode image: {}", e)))
+}
+
+// 取 multipart 中第一个带 filename 的字段, 没有则取第一个字段
+fn multipart_file(body: &[u8], boundary: &str) -> Option<Vec<u8>> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut rest = body;
+    while let Some(start) = find(rest, delimiter.as_bytes()) {
+        rest = &rest[start + delimiter.len()..];
+        if rest.starts_with(b"--") {
+            break;
+        }
+        let end = find(rest, delimiter.as_bytes()).unwrap_or(rest.len());
+        let part = &rest[..end];
+        let header_end = find(part, b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&part[..header_end]).to_string();
+        let content = part[header_end + 4..].strip_suffix(b"\r\n").unwrap_or(&part[header_end + 4..]);
+        parts.push((headers, content.to_vec()));
+    }
+
+    let idx = parts.iter().position(|(h, _)| h.contains("filename=")).unwrap_or(0);
+    parts.into_iter().nth(idx).map(|(_, content)| content)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
@@ -0,0 +1,371 @@
+// src/simd.rs
+//
+// 运行时 CPU 特性分发的 SIMD 内核, 只依赖 stable 的 std::arch。
+// 默认选择当前 CPU 支持的最快后端, 可以用环境变量 COGVLM_SIMD=scalar|avx2|avx512|neon 强制指定。
+
+use std::sync::OnceLock;
+
+pub const BACKEND_ENV: &str = "COGVLM_SIMD";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimdBackend {
+    Scalar,
+    Avx2,
+    Avx512,
+    Neon,
+}
+
+impl SimdBackend {
+    pub const ALL: [SimdBackend; 4] = [SimdBackend::Scalar, SimdBackend::Avx2, SimdBackend::Avx512, SimdBackend::Neon];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SimdBackend::Scalar => "scalar",
+            SimdBackend::Avx2 => "avx2",
+            SimdBackend::Avx512 => "avx512",
+            SimdBackend::Neon => "neon",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        SimdBackend::ALL.into_iter().find(|b| b.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn is_supported(self) -> bool {
+        match self {
+            SimdBackend::Scalar => true,
+            #[cfg(target_arch = "x86_64")]
+            SimdBackend::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(target_arch = "x86_64")]
+            SimdBackend::Avx512 => is_x86_feature_detected!("avx512f"),
+            #[cfg(target_arch = "aarch64")]
+            SimdBackend::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// 当前 CPU 支持的全部后端
+    pub fn available() -> Vec<SimdBackend> {
+        SimdBackend::ALL.into_iter().filter(|b| b.is_supported()).collect()
+    }
+}
+
+/// 当前 CPU 上最快的后端
+pub fn detect() -> SimdBackend {
+    [SimdBackend::Avx512, SimdBackend::Avx2, SimdBackend::Neon]
+        .into_iter()
+        .find(|b| b.is_supported())
+        .unwrap_or(SimdBackend::Scalar)
+}
+
+/// 进程内使用的后端, 首次调用时读取 COGVLM_SIMD
+pub fn backend() -> SimdBackend {
+    static BACKEND: OnceLock<SimdBackend> = OnceLock::new();
+    *BACKEND.get_or_init(|| match std::env::var(BACKEND_ENV) {
+        Ok(name) => match SimdBackend::from_name(&name) {
+            Some(b) if b.is_supported() => b,
+            _ => {
+                let fallback = detect();
+                eprintln!("{}={} is not available on this CPU, using {}", BACKEND_ENV, name, fallback.name());
+                fallback
+            }
+        },
+        Err(_) => detect(),
+    })
+}
+
+fn check(backend: SimdBackend) {
+    assert!(backend.is_supported(), "SIMD backend {} is not supported on this CPU", backend.name());
+}
+
+/// out = src
+pub fn copy(src: &[f32], out: &mut [f32]) {
+    copy_with(backend(), src, out)
+}
+
+pub fn copy_with(backend: SimdBackend, src: &[f32], out: &mut [f32]) {
+    check(backend);
+    assert_eq!(src.len(), out.len());
+    match backend {
+        #[cfg(target_arch = "x86_64")]
+        SimdBackend::Avx2 => unsafe { x86::copy_avx2(src, out) },
+        #[cfg(target_arch = "x86_64")]
+        SimdBackend::Avx512 => unsafe { x86::copy_avx512(src, out) },
+        #[cfg(target_arch = "aarch64")]
+        SimdBackend::Neon => unsafe { arm::copy_neon(src, out) },
+        _ => scalar::copy(src, out),
+    }
+}
+
+/// out = value * relu(gate)
+pub fn glu(value: &[f32], gate: &[f32], out: &mut [f32]) {
+    glu_with(backend(), value, gate, out)
+}
+
+pub fn glu_with(backend: SimdBackend, value: &[f32], gate: &[f32], out: &mut [f32]) {
+    check(backend);
+    assert!(value.len() == out.len() && gate.len() == out.len());
+    match backend {
+        #[cfg(target_arch = "x86_64")]
+        SimdBackend::Avx2 => unsafe { x86::glu_avx2(value, gate, out) },
+        #[cfg(target_arch = "x86_64")]
+        SimdBackend::Avx512 => unsafe { x86::glu_avx512(value, gate, out) },
+        #[cfg(target_arch = "aarch64")]
+        SimdBackend::Neon => unsafe { arm::glu_neon(value, gate, out) },
+        _ => scalar::glu(value, gate, out),
+    }
+}
+
+/// 交错布局的 RoPE: row[j] = row[j] * cos[j] + row[j ^ 1] * sin[j], cos.len() 须为偶数
+pub fn rope_interleaved(row: &mut [f32], cos: &[f32], sin: &[f32]) {
+    rope_interleaved_with(backend(), row, cos, sin)
+}
+
+pub fn rope_interleaved_with(backend: SimdBackend, row: &mut [f32], cos: &[f32], sin: &[f32]) {
+    check(backend);
+    assert!(cos.len().is_multiple_of(2) && sin.len() == cos.len() && row.len() >= cos.len());
+    let row = &mut row[..cos.len()];
+    match backend {
+        #[cfg(target_arch = "x86_64")]
+        SimdBackend::Avx2 => unsafe { x86::rope_interleaved_avx2(row, cos, sin) },
+        #[cfg(target_arch = "x86_64")]
+        SimdBackend::Avx512 => unsafe { x86::rope_interleaved_avx512(row, cos, sin) },
+        #[cfg(target_arch = "aarch64")]
+        SimdBackend::Neon => unsafe { arm::rope_interleaved_neon(row, cos, sin) },
+        _ => scalar::rope_interleaved(row, cos, sin),
+    }
+}
+
+/// rotate-half 布局的 RoPE: 前半 lo 与后半 hi 成对, cos/sin 覆盖两半 (长度 2 * lo.len())
+pub fn rope_half(lo: &mut [f32], hi: &mut [f32], cos: &[f32], sin: &[f32]) {
+    rope_half_with(backend(), lo, hi, cos, sin)
+}
+
+pub fn rope_half_with(backend: SimdBackend, lo: &mut [f32], hi: &mut [f32], cos: &[f32], sin: &[f32]) {
+    check(backend);
+    let half = lo.len();
+    assert!(hi.len() == half && cos.len() == 2 * half && sin.len() == 2 * half);
+    let (cos_lo, cos_hi) = cos.split_at(half);
+    let (sin_lo, sin_hi) = sin.split_at(half);
+    match backend {
+        #[cfg(target_arch = "x86_64")]
+        SimdBackend::Avx2 => unsafe { x86::rope_half_avx2(lo, hi, cos_lo, sin_lo, cos_hi, sin_hi) },
+        #[cfg(target_arch = "x86_64")]
+        SimdBackend::Avx512 => unsafe { x86::rope_half_avx512(lo, hi, cos_lo, sin_lo, cos_hi, sin_hi) },
+        #[cfg(target_arch = "aarch64")]
+        SimdBackend::Neon => unsafe { arm::rope_half_neon(lo, hi, cos_lo, sin_lo, cos_hi, sin_hi) },
+        _ => scalar::rope_half(lo, hi, cos_lo, sin_lo, cos_hi, sin_hi),
+    }
+}
+
+// 标量实现, 同时用作各后端的尾部处理
+mod scalar {
+    pub(super) fn copy(src: &[f32], out: &mut [f32]) {
+        out.copy_from_slice(src);
+    }
+
+    pub(super) fn glu(value: &[f32], gate: &[f32], out: &mut [f32]) {
+        for ((o, &v), &g) in out.iter_mut().zip(value).zip(gate) {
+            *o = v * g.max(0.0);
+        }
+    }
+
+    pub(super) fn rope_interleaved(row: &mut [f32], cos: &[f32], sin: &[f32]) {
+        for j in (0..cos.len()).step_by(2) {
+            let (x0, x1) = (row[j], row[j + 1]);
+            row[j] = x0 * cos[j] + x1 * sin[j];
+            row[j + 1] = x1 * cos[j + 1] + x0 * sin[j + 1];
+        }
+    }
+
+    pub(super) fn rope_half(lo: &mut [f32], hi: &mut [f32], cos_lo: &[f32], sin_lo: &[f32], cos_hi: &[f32], sin_hi: &[f32]) {
+        for j in 0..lo.len() {
+            let (x0, x1) = (lo[j], hi[j]);
+            lo[j] = x0 * cos_lo[j] + x1 * sin_lo[j];
+            hi[j] = x1 * cos_hi[j] + x0 * sin_hi[j];
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::scalar;
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn copy_avx2(src: &[f32], out: &mut [f32]) {
+        let n = src.len();
+        let mut i = 0;
+        while i + 8 <= n {
+            _mm256_storeu_ps(out.as_mut_ptr().add(i), _mm256_loadu_ps(src.as_ptr().add(i)));
+            i += 8;
+        }
+        scalar::copy(&src[i..], &mut out[i..]);
+    }
+
+    #[target_feature(enable = "avx512f")]
+    pub(super) unsafe fn copy_avx512(src: &[f32], out: &mut [f32]) {
+        let n = src.len();
+        let mut i = 0;
+        while i + 16 <= n {
+            _mm512_storeu_ps(out.as_mut_ptr().add(i), _mm512_loadu_ps(src.as_ptr().add(i)));
+            i += 16;
+        }
+        scalar::copy(&src[i..], &mut out[i..]);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn glu_avx2(value: &[f32], gate: &[f32], out: &mut [f32]) {
+        let n = out.len();
+        let zero = _mm256_setzero_ps();
+        let mut i = 0;
+        while i + 8 <= n {
+            let v = _mm256_loadu_ps(value.as_ptr().add(i));
+            let g = _mm256_max_ps(_mm256_loadu_ps(gate.as_ptr().add(i)), zero);
+            _mm256_storeu_ps(out.as_mut_ptr().add(i), _mm256_mul_ps(v, g));
+            i += 8;
+        }
+        scalar::glu(&value[i..], &gate[i..], &mut out[i..]);
+    }
+
+    #[target_feature(enable = "avx512f")]
+    pub(super) unsafe fn glu_avx512(value: &[f32], gate: &[f32], out: &mut [f32]) {
+        let n = out.len();
+        let zero = _mm512_setzero_ps();
+        let mut i = 0;
+        while i + 16 <= n {
+            let v = _mm512_loadu_ps(value.as_ptr().add(i));
+            let g = _mm512_max_ps(_mm512_loadu_ps(gate.as_ptr().add(i)), zero);
+            _mm512_storeu_ps(out.as_mut_ptr().add(i), _mm512_mul_ps(v, g));
+            i += 16;
+        }
+        scalar::glu(&value[i..], &gate[i..], &mut out[i..]);
+    }
+
+    // 0xB1 在每个 128 位通道内交换相邻元素: [1, 0, 3, 2]
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn rope_interleaved_avx2(row: &mut [f32], cos: &[f32], sin: &[f32]) {
+        let n = cos.len();
+        let mut j = 0;
+        while j + 8 <= n {
+            let x = _mm256_loadu_ps(row.as_ptr().add(j));
+            let swapped = _mm256_permute_ps::<0xB1>(x);
+            let c = _mm256_loadu_ps(cos.as_ptr().add(j));
+            let s = _mm256_loadu_ps(sin.as_ptr().add(j));
+            _mm256_storeu_ps(row.as_mut_ptr().add(j), _mm256_add_ps(_mm256_mul_ps(x, c), _mm256_mul_ps(swapped, s)));
+            j += 8;
+        }
+        scalar::rope_interleaved(&mut row[j..], &cos[j..], &sin[j..]);
+    }
+
+    #[target_feature(enable = "avx512f")]
+    pub(super) unsafe fn rope_interleaved_avx512(row: &mut [f32], cos: &[f32], sin: &[f32]) {
+        let n = cos.len();
+        let mut j = 0;
+        while j + 16 <= n {
+            let x = _mm512_loadu_ps(row.as_ptr().add(j));
+            let swapped = _mm512_permute_ps::<0xB1>(x);
+            let c = _mm512_loadu_ps(cos.as_ptr().add(j));
+            let s = _mm512_loadu_ps(sin.as_ptr().add(j));
+            _mm512_storeu_ps(row.as_mut_ptr().add(j), _mm512_fmadd_ps(x, c, _mm512_mul_ps(swapped, s)));
+            j += 16;
+        }
+        scalar::rope_interleaved(&mut row[j..], &cos[j..], &sin[j..]);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn rope_half_avx2(lo: &mut [f32], hi: &mut [f32], cos_lo: &[f32], sin_lo: &[f32], cos_hi: &[f32], sin_hi: &[f32]) {
+        let half = lo.len();
+        let mut j = 0;
+        while j + 8 <= half {
+            let x0 = _mm256_loadu_ps(lo.as_ptr().add(j));
+            let x1 = _mm256_loadu_ps(hi.as_ptr().add(j));
+            let (c0, s0) = (_mm256_loadu_ps(cos_lo.as_ptr().add(j)), _mm256_loadu_ps(sin_lo.as_ptr().add(j)));
+            let (c1, s1) = (_mm256_loadu_ps(cos_hi.as_ptr().add(j)), _mm256_loadu_ps(sin_hi.as_ptr().add(j)));
+            _mm256_storeu_ps(lo.as_mut_ptr().add(j), _mm256_add_ps(_mm256_mul_ps(x0, c0), _mm256_mul_ps(x1, s0)));
+            _mm256_storeu_ps(hi.as_mut_ptr().add(j), _mm256_add_ps(_mm256_mul_ps(x1, c1), _mm256_mul_ps(x0, s1)));
+            j += 8;
+        }
+        scalar::rope_half(&mut lo[j..], &mut hi[j..], &cos_lo[j..], &sin_lo[j..], &cos_hi[j..], &sin_hi[j..]);
+    }
+
+    #[target_feature(enable = "avx512f")]
+    pub(super) unsafe fn rope_half_avx512(lo: &mut [f32], hi: &mut [f32], cos_lo: &[f32], sin_lo: &[f32], cos_hi: &[f32], sin_hi: &[f32]) {
+        let half = lo.len();
+        let mut j = 0;
+        while j + 16 <= half {
+            let x0 = _mm512_loadu_ps(lo.as_ptr().add(j));
+            let x1 = _mm512_loadu_ps(hi.as_ptr().add(j));
+            let (c0, s0) = (_mm512_loadu_ps(cos_lo.as_ptr().add(j)), _mm512_loadu_ps(sin_lo.as_ptr().add(j)));
+            let (c1, s1) = (_mm512_loadu_ps(cos_hi.as_ptr().add(j)), _mm512_loadu_ps(sin_hi.as_ptr().add(j)));
+            _mm512_storeu_ps(lo.as_mut_ptr().add(j), _mm512_fmadd_ps(x0, c0, _mm512_mul_ps(x1, s0)));
+            _mm512_storeu_ps(hi.as_mut_ptr().add(j), _mm512_fmadd_ps(x1, c1, _mm512_mul_ps(x0, s1)));
+            j += 16;
+        }
+        scalar::rope_half(&mut lo[j..], &mut hi[j..], &cos_lo[j..], &sin_lo[j..], &cos_hi[j..], &sin_hi[j..]);
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arm {
+    use super::scalar;
+    use std::arch::aarch64::*;
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn copy_neon(src: &[f32], out: &mut [f32]) {
+        let n = src.len();
+        let mut i = 0;
+        while i + 4 <= n {
+            vst1q_f32(out.as_mut_ptr().add(i), vld1q_f32(src.as_ptr().add(i)));
+            i += 4;
+        }
+        scalar::copy(&src[i..], &mut out[i..]);
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn glu_neon(value: &[f32], gate: &[f32], out: &mut [f32]) {
+        let n = out.len();
+        let zero = vdupq_n_f32(0.0);
+        let mut i = 0;
+        while i + 4 <= n {
+            let v = vld1q_f32(value.as_ptr().add(i));
+            let g = vmaxq_f32(vld1q_f32(gate.as_ptr().add(i)), zero);
+            vst1q_f32(out.as_mut_ptr().add(i), vmulq_f32(v, g));
+            i += 4;
+        }
+        scalar::glu(&value[i..], &gate[i..], &mut out[i..]);
+    }
+
+    // vrev64q_f32 交换每 64 位内的两个元素: [1, 0, 3, 2]
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn rope_interleaved_neon(row: &mut [f32], cos: &[f32], sin: &[f32]) {
+        let n = cos.len();
+        let mut j = 0;
+        while j + 4 <= n {
+            let x = vld1q_f32(row.as_ptr().add(j));
+            let swapped = vrev64q_f32(x);
+            let c = vld1q_f32(cos.as_ptr().add(j));
+            let s = vld1q_f32(sin.as_ptr().add(j));
+            vst1q_f32(row.as_mut_ptr().add(j), vfmaq_f32(vmulq_f32(swapped, s), x, c));
+            j += 4;
+        }
+        scalar::rope_interleaved(&mut row[j..], &cos[j..], &sin[j..]);
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn rope_half_neon(lo: &mut [f32], hi: &mut [f32], cos_lo: &[f32], sin_lo: &[f32], cos_hi: &[f32], sin_hi: &[f32]) {
+        let half = lo.len();
+        let mut j = 0;
+        while j + 4 <= half {
+            let x0 = vld1q_f32(lo.as_ptr().add(j));
+            let x1 = vld1q_f32(hi.as_ptr().add(j));
+            let (c0, s0) = (vld1q_f32(cos_lo.as_ptr().add(j)), vld1q_f32(sin_lo.as_ptr().add(j)));
+            let (c1, s1) = (vld1q_f32(cos_hi.as_ptr().add(j)), vld1q_f32(sin_hi.as_ptr().add(j)));
+            vst1q_f32(lo.as_mut_ptr().add(j), vfmaq_f32(vmulq_f32(x1, s0), x0, c0));
+            vst1q_f32(hi.as_mut_ptr().add(j), vfmaq_f32(vmulq_f32(x0, s1), x1, c1));
+            j += 4;
+        }
+        scalar::rope_half(&mut lo[j..], &mut hi[j..], &cos_lo[j..], &sin_lo[j..], &cos_hi[j..], &sin_hi[j..]);
+    }
+}
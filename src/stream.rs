@@ -0,0 +1,192 @@
+// src/stream.rs
+//
+// 流式预处理: 输入可以是路径、内存中的编码字节或 file:// URL, 每次取至多 batch_size 个输入,
+// 在线程池中并行解码后按 `process_images_in_batch` 的方式写入该批的 (n, 3, S, S)。
+// 后台线程提前处理至多 prefetch 批, 调用方编码当前批时下一批已在解码; 内存中同时至多 prefetch + 2 批。
+// 解码失败的输入作为该批的逐项错误返回, 不影响同批的其他输入
+//
+//   for batch in processor.stream(paths.clone(), &StreamConfig::default())? {
+//       let embeddings = encoder.forward_batch(...);   // batch.indices[i] 为第 i 张在 paths 中的下标
+//       for (i, err) in &batch.errors { ... }
+//   }
+
+use image::{DynamicImage, ImageError, ImageResult};
+use rayon::prelude::*;
+use rayon::{ThreadPoolBuildError, ThreadPoolBuilder};
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+use crate::processor::{process_indexed_in_batch, ImageBatchOutput, ImageProcessor};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageSource {
+    Path(PathBuf),
+    /// 编码后的文件内容 (JPEG / PNG 等), 格式按内容识别
+    Bytes(Vec<u8>),
+    /// 只读取 file:// URL 指向的本地文件 (不做百分号解码), 其他协议返回错误
+    Url(String),
+}
+
+impl ImageSource {
+    pub fn decode(&self) -> ImageResult<DynamicImage> {
+        match self {
+            ImageSource::Path(path) => image::open(path),
+            ImageSource::Bytes(bytes) => image::load_from_memory(bytes),
+            ImageSource::Url(url) => image::open(file_url_path(url)?),
+        }
+    }
+
+    /// 记入 `ImageMetadata::source` 的本地路径
+    pub fn path(&self) -> Option<PathBuf> {
+        match self {
+            ImageSource::Path(path) => Some(path.clone()),
+            ImageSource::Bytes(_) => None,
+            ImageSource::Url(url) => file_url_path(url).ok(),
+        }
+    }
+}
+
+fn file_url_path(url: &str) -> ImageResult<PathBuf> {
+    url.strip_prefix("file://").map(PathBuf::from).ok_or_else(|| {
+        let message = format!("unsupported URL {} (only file:// is supported)", url);
+        ImageError::IoError(io::Error::new(io::ErrorKind::Unsupported, message))
+    })
+}
+
+impl From<PathBuf> for ImageSource {
+    fn from(path: PathBuf) -> Self {
+        ImageSource::Path(path)
+    }
+}
+
+impl From<&Path> for ImageSource {
+    fn from(path: &Path) -> Self {
+        ImageSource::Path(path.to_path_buf())
+    }
+}
+
+impl From<&PathBuf> for ImageSource {
+    fn from(path: &PathBuf) -> Self {
+        ImageSource::Path(path.clone())
+    }
+}
+
+impl From<Vec<u8>> for ImageSource {
+    fn from(bytes: Vec<u8>) -> Self {
+        ImageSource::Bytes(bytes)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamConfig {
+    /// 每批的输入数, 最后一批可能较少
+    pub batch_size: usize,
+    /// 解码和预处理使用的线程数, None 时使用 rayon 的全局线程池
+    pub num_threads: Option<usize>,
+    /// 处理完、等待 `next()` 取走的批数上限; 后台线程另外至多在处理一批
+    pub prefetch: usize,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        StreamConfig { batch_size: 32, num_threads: None, prefetch: 1 }
+    }
+}
+
+/// 一批输入的结果; 成功和失败的下标合起来恰好是这一批的输入
+pub struct StreamBatch {
+    /// 成功解码的图像, 第 i 张对应 indices[i]
+    pub output: ImageBatchOutput,
+    /// 成功的输入在整个流中的下标
+    pub indices: Vec<usize>,
+    /// 失败的输入 (在整个流中的下标, 错误)
+    pub errors: Vec<(usize, ImageError)>,
+}
+
+pub struct BatchStream {
+    batches: Receiver<StreamBatch>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl ImageProcessor {
+    /// 按 config 把 sources 分批预处理; 增强的随机数下标取输入在整个流中的下标,
+    /// 因此结果与批大小无关, 与对全部输入调用 `process_images_in_batch` 相同。
+    /// sources 在后台线程中读取, 丢弃 `BatchStream` 后该线程处理完手上的一批即退出
+    pub fn stream<I>(&self, sources: I, config: &StreamConfig) -> Result<BatchStream, ThreadPoolBuildError>
+    where
+        I: IntoIterator,
+        I::IntoIter: Send + 'static,
+        I::Item: Into<ImageSource>,
+    {
+        assert!(config.batch_size > 0, "batch_size must be positive");
+        let pool = config.num_threads.map(|n| ThreadPoolBuilder::new().num_threads(n).build()).transpose()?;
+        let (sender, batches) = mpsc::sync_channel(config.prefetch);
+        let (processor, batch_size, mut sources) = (self.clone(), config.batch_size, sources.into_iter());
+
+        let worker = thread::spawn(move || {
+            let mut next_index = 0;
+            loop {
+                let chunk: Vec<(usize, ImageSource)> =
+                    sources.by_ref().take(batch_size).enumerate().map(|(i, s)| (next_index + i, s.into())).collect();
+                if chunk.is_empty() {
+                    return;
+                }
+                next_index += chunk.len();
+
+                let run = || process_chunk(&processor, chunk);
+                let batch = match &pool {
+                    Some(pool) => pool.install(run),
+                    None => run(),
+                };
+                // 接收端已丢弃
+                if sender.send(batch).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(BatchStream { batches, worker: Some(worker) })
+    }
+}
+
+impl Iterator for BatchStream {
+    type Item = StreamBatch;
+
+    fn next(&mut self) -> Option<StreamBatch> {
+        if let Ok(batch) = self.batches.recv() {
+            return Some(batch);
+        }
+        // 后台线程已退出: 输入取完, 或者处理时 panic, 后者在调用方线程中重新抛出
+        if let Some(Err(payload)) = self.worker.take().map(JoinHandle::join) {
+            panic::resume_unwind(payload);
+        }
+        None
+    }
+}
+
+fn process_chunk(processor: &ImageProcessor, chunk: Vec<(usize, ImageSource)>) -> StreamBatch {
+    let decoded: Vec<_> = chunk
+        .into_par_iter()
+        .map(|(i, source)| (i, source.decode().map(|img| (img, source.path()))))
+        .collect();
+
+    let (mut images, mut sources, mut indices, mut errors) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for (i, result) in decoded {
+        match result {
+            Ok((image, source)) => {
+                images.push(image);
+                sources.push(source);
+                indices.push(i);
+            }
+            Err(err) => errors.push((i, err)),
+        }
+    }
+
+    let stream_indices: Vec<u64> = indices.iter().map(|&i| i as u64).collect();
+    let mut output = process_indexed_in_batch(&images, &stream_indices, processor);
+    for (meta, source) in output.metadata.iter_mut().zip(sources) {
+        meta.source = source;
+    }
+    StreamBatch { output, indices, errors }
+}
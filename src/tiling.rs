@@ -0,0 +1,100 @@
+// src/tiling.rs
+//
+// 高分辨率切片 (CogVLM2 / LLaVA-NeXT / InternVL 的做法):
+// 按宽高比选出 rows x cols 网格, 把原图缩放到 (cols * S, rows * S) 后切成 S x S 的块 (S = image_size),
+// 另加一张整图缩放到 S x S 的缩略图; 各块分别送入编码器
+
+use image::DynamicImage;
+use ndarray::{Array2, Array3};
+use rayon::prelude::*;
+use crate::processor::{resize_bicubic_window, ImageProcessor};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileConfig {
+    /// 网格的最大块数 (rows * cols)
+    pub max_tiles: usize,
+    /// 是否附加全局缩略图
+    pub thumbnail: bool,
+}
+
+impl Default for TileConfig {
+    fn default() -> Self {
+        TileConfig { max_tiles: 6, thumbnail: true }
+    }
+}
+
+/// 网格中 (row, col) 位置的一块, image 为归一化的 (3, S, S)
+pub struct Tile {
+    pub row: usize,
+    pub col: usize,
+    pub image: Array3<f32>,
+}
+
+pub struct TiledImage {
+    /// (rows, cols)
+    pub grid: (usize, usize),
+    /// 按行优先排列
+    pub tiles: Vec<Tile>,
+    pub thumbnail: Option<Array3<f32>>,
+}
+
+pub struct TileEmbedding {
+    pub row: usize,
+    pub col: usize,
+    pub embedding: Array2<f32>,
+}
+
+/// `VisionEncoder::forward_tiled` 的输出, 与 `TiledImage` 一一对应
+pub struct TiledEmbeddings {
+    pub grid: (usize, usize),
+    pub tiles: Vec<TileEmbedding>,
+    pub thumbnail: Option<Array2<f32>>,
+}
+
+/// 在 rows * cols <= max_tiles 的网格中选宽高比最接近 width / height 的一个;
+/// 比例相同时, 若原图像素多于较大网格的一半则取较大的网格
+pub fn select_grid(width: u32, height: u32, tile_size: u32, max_tiles: usize) -> (usize, usize) {
+    let aspect = width as f32 / height as f32;
+    let area = width as f32 * height as f32;
+    let mut best = (1, 1);
+    let mut best_diff = f32::INFINITY;
+    for n in 1..=max_tiles.max(1) {
+        for cols in (1..=n).filter(|c| n.is_multiple_of(*c)) {
+            let rows = n / cols;
+            let diff = (aspect - cols as f32 / rows as f32).abs();
+            if diff < best_diff {
+                best = (rows, cols);
+                best_diff = diff;
+            } else if diff == best_diff && area > 0.5 * (tile_size * tile_size) as f32 * n as f32 {
+                best = (rows, cols);
+            }
+        }
+    }
+    best
+}
+
+impl ImageProcessor {
+    /// 切片预处理, 块大小为 image_size; 各块只计算自己窗口内的缩放, 并行处理
+    pub fn preprocess_tiled(&self, img: &DynamicImage, config: &TileConfig) -> TiledImage {
+        let rgb = img.to_rgb8();
+        let size = self.image_size;
+        let (rows, cols) = select_grid(rgb.width(), rgb.height(), size, config.max_tiles);
+        let (out_w, out_h) = (cols as u32 * size, rows as u32 * size);
+
+        let (tiles, thumbnail) = rayon::join(
+            || {
+                (0..rows * cols)
+                    .into_par_iter()
+                    .map(|i| {
+                        let (row, col) = (i / cols, i % cols);
+                        let window = (col as u32 * size, row as u32 * size, size, size);
+                        let image = self.normalize(&resize_bicubic_window(&rgb, out_w, out_h, window));
+                        Tile { row, col, image }
+                    })
+                    .collect()
+            },
+            || config.thumbnail.then(|| self.preprocess(img)),
+        );
+        TiledImage { grid: (rows, cols), tiles, thumbnail }
+    }
+}
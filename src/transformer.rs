@@ -1,11 +1,16 @@
-use ndarray::{Array2, Axis, Zip, s};
-use ndarray_rand::RandomExt;
-use rand_distr::Uniform;
+use ndarray::{Array1, Array2, Array3, ArrayBase, ArrayView2, ArrayViewMut2, Axis, Data, Ix2, OwnedRepr, Zip, s};
+use ndarray::linalg::general_mat_mul;
+use rayon::prelude::*;
+use rand::Rng;
+use crate::capture::ActivationCapture;
+use crate::init::Initializer;
+use crate::rope::RopeCache;
+use std::sync::Arc;
 
-pub struct LayerNorm {
+pub struct LayerNorm<S: Data<Elem = f32> = OwnedRepr<f32>> {
     pub epsilon: f32,
-    pub gamma: Array2<f32>, // (1, dim)
-    pub beta: Array2<f32>,  // (1, dim)
+    pub gamma: ArrayBase<S, Ix2>, // (1, dim)
+    pub beta: ArrayBase<S, Ix2>,  // (1, dim)
 }
 
 impl LayerNorm {
@@ -17,6 +22,12 @@ impl LayerNorm {
         }
     }
 
+    pub fn parameters_mut(&mut self) -> Vec<&mut Array2<f32>> {
+        vec![&mut self.gamma, &mut self.beta]
+    }
+}
+
+impl<S: Data<Elem = f32> + Sync> LayerNorm<S> {
     pub fn forward(&self, x: &Array2<f32>) -> Array2<f32> {
         let mean = x.mean_axis(Axis(1)).unwrap();
         let var = x.var_axis(Axis(1), 0.0);
@@ -33,62 +44,394 @@ impl LayerNorm {
         // 逐元素乘gamma+beta
         normalized * &self.gamma + &self.beta
     }
+
+    // 训练用前向, 额外返回反向所需的缓存
+    pub fn forward_train(&self, x: &Array2<f32>) -> (Array2<f32>, LayerNormCache) {
+        let mean = x.mean_axis(Axis(1)).unwrap();
+        let var = x.var_axis(Axis(1), 0.0);
+        let inv_std = var.mapv(|v| 1.0 / (v + self.epsilon).sqrt());
+
+        let mut x_hat = x.clone();
+        Zip::from(x_hat.rows_mut())
+            .and(&mean)
+            .and(&inv_std)
+            .for_each(|mut row, &m, &inv| {
+                row -= m;
+                row *= inv;
+            });
+
+        let out = &x_hat * &self.gamma + &self.beta;
+        (out, LayerNormCache { x_hat, inv_std })
+    }
+
+    pub fn backward(&self, cache: &LayerNormCache, grad_out: &Array2<f32>) -> (Array2<f32>, LayerNormGrads) {
+        let grads = LayerNormGrads {
+            gamma: (grad_out * &cache.x_hat).sum_axis(Axis(0)).insert_axis(Axis(0)),
+            beta: grad_out.sum_axis(Axis(0)).insert_axis(Axis(0)),
+        };
+
+        // dx = inv_std * (dx_hat - mean(dx_hat) - x_hat * mean(dx_hat * x_hat)), 按行
+        let dx_hat = grad_out * &self.gamma;
+        let mut dx = dx_hat.clone();
+        Zip::from(dx.rows_mut())
+            .and(cache.x_hat.rows())
+            .and(&cache.inv_std)
+            .for_each(|mut row, x_hat, &inv| {
+                let mean_d = row.mean().unwrap();
+                let mean_dx = row.dot(&x_hat) / row.len() as f32;
+                Zip::from(&mut row).and(&x_hat).for_each(|d, &xh| {
+                    *d = inv * (*d - mean_d - xh * mean_dx);
+                });
+            });
+        (dx, grads)
+    }
+}
+
+pub struct LayerNormCache {
+    x_hat: Array2<f32>,
+    inv_std: Array1<f32>,
+}
+
+pub struct LayerNormGrads {
+    pub gamma: Array2<f32>,
+    pub beta: Array2<f32>,
+}
+
+impl LayerNormGrads {
+    // 与 LayerNorm::parameters_mut 顺序一致
+    pub fn tensors(&self) -> Vec<&Array2<f32>> {
+        vec![&self.gamma, &self.beta]
+    }
 }
 
 // 激活函数gelu
 fn gelu(x: f32) -> f32 {
-    0.5 * x * (1.0 + (x * 0.7978845608 * (1.0 + 0.044715 * x * x)).tanh())
+    0.5 * x * (1.0 + (x * 0.797_884_6 * (1.0 + 0.044715 * x * x)).tanh())
 }
 
 fn gelu_array(x: &Array2<f32>) -> Array2<f32> {
     x.mapv(gelu)
 }
 
+// gelu (tanh 近似) 的导数
+fn gelu_grad(x: f32) -> f32 {
+    let c = 0.797_884_6;
+    let t = (x * c * (1.0 + 0.044715 * x * x)).tanh();
+    0.5 * (1.0 + t) + 0.5 * x * (1.0 - t * t) * c * (1.0 + 3.0 * 0.044715 * x * x)
+}
+
 fn scaled_dot_product_attention(
     q: &Array2<f32>, 
     k: &Array2<f32>, 
     v: &Array2<f32>, 
 ) -> Array2<f32> {
     let dk = q.shape()[1] as f32;
-    let scores = q.dot(&k.t()) / dk.sqrt();
-    
-    let mut exp_scores = scores.mapv(f32::exp);
-    for mut row in exp_scores.outer_iter_mut() {
-        let sum = row.sum();
-        if sum > 0.0 {
-            row /= sum;
+    let mut scores = q.dot(&k.t()) / dk.sqrt();
+    softmax_rows(&mut scores, None);
+
+    scores.dot(v)
+}
+
+// 逐行 softmax, exp 前减去该行最大值以免溢出;
+// mask 为 false 的位置按 -inf 处理, 整行被屏蔽时该行全为 0
+fn softmax_rows(scores: &mut Array2<f32>, mask: Option<&Array2<bool>>) {
+    if let Some(mask) = mask {
+        Zip::from(&mut *scores).and(mask).for_each(|s, &keep| {
+            if !keep {
+                *s = f32::NEG_INFINITY;
+            }
+        });
+    }
+    for mut row in scores.outer_iter_mut() {
+        let max = row.fold(f32::NEG_INFINITY, |m, &s| m.max(s));
+        if max == f32::NEG_INFINITY {
+            row.fill(0.0);
+            continue;
         }
+        row.mapv_inplace(|s| (s - max).exp());
+        let sum = row.sum();
+        row /= sum;
     }
+}
+
+// 与 scaled_dot_product_attention 相同, 结果直接写入 out;
+// mask (q_len, k_len) 为 false 的位置不参与注意力, 整行被屏蔽时输出为 0
+fn attention_into(
+    q: ArrayView2<f32>,
+    k: ArrayView2<f32>,
+    v: ArrayView2<f32>,
+    mask: Option<&Array2<bool>>,
+    mut out: ArrayViewMut2<f32>,
+) {
+    let dk = q.shape()[1] as f32;
+    let mut scores = q.dot(&k.t()) / dk.sqrt();
+    softmax_rows(&mut scores, mask);
 
-    exp_scores.dot(v)
+    general_mat_mul(1.0, &scores, &v, 0.0, &mut out);
 }
 
-// 多头自注意力
-pub struct MultiHeadAttention {
+// (seq_len, num_heads * head_dim) -> 连续的 (num_heads, seq_len, head_dim)
+fn split_heads(x: Array2<f32>, num_heads: usize, head_dim: usize) -> Array3<f32> {
+    let seq_len = x.shape()[0];
+    x.into_shape((seq_len, num_heads, head_dim))
+        .unwrap()
+        .permuted_axes([1, 0, 2])
+        .as_standard_layout()
+        .into_owned()
+}
+
+// 多头自注意力, num_kv_heads < num_heads 时为 GQA (num_kv_heads == 1 即 MQA),
+// 每 num_heads / num_kv_heads 个 Q head 共享一组 K/V
+pub struct MultiHeadAttention<S: Data<Elem = f32> = OwnedRepr<f32>> {
     pub num_heads: usize,
+    pub num_kv_heads: usize,
     pub head_dim: usize,
-    pub wq: Array2<f32>, // (embed_dim, num_heads * head_dim)
-    pub wk: Array2<f32>,
-    pub wv: Array2<f32>,
-    pub wo: Array2<f32>,
+    pub wq: ArrayBase<S, Ix2>, // (embed_dim, num_heads * head_dim)
+    pub wk: ArrayBase<S, Ix2>, // (embed_dim, num_kv_heads * head_dim)
+    pub wv: ArrayBase<S, Ix2>, // (embed_dim, num_kv_heads * head_dim)
+    pub wo: ArrayBase<S, Ix2>,
+    // 可选的旋转位置编码, 在 Q/K 投影后按 head 施加 (RopeConfig.dim 须等于 head_dim)
+    pub rope: Option<Arc<RopeCache>>,
 }
 
 impl MultiHeadAttention {
     pub fn new(embed_dim: usize, num_heads: usize) -> Self {
-        let head_dim = embed_dim / num_heads;
-        let dist = Uniform::new(-0.1, 0.1);
+        Self::with_kv_heads(embed_dim, num_heads, num_heads)
+    }
+
+    pub fn with_kv_heads(embed_dim: usize, num_heads: usize, num_kv_heads: usize) -> Self {
+        Self::cross(embed_dim, embed_dim, num_heads, num_kv_heads)
+    }
+
+    // 交叉注意力: Q 来自 query_dim 维的序列, K/V 来自 kv_dim 维的序列, 输出回到 query_dim
+    pub fn cross(query_dim: usize, kv_dim: usize, num_heads: usize, num_kv_heads: usize) -> Self {
+        Self::cross_with_rng(query_dim, kv_dim, num_heads, num_kv_heads, &mut rand::thread_rng())
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(embed_dim: usize, num_heads: usize, num_kv_heads: usize, rng: &mut R) -> Self {
+        Self::cross_with_rng(embed_dim, embed_dim, num_heads, num_kv_heads, rng)
+    }
+
+    // 投影权重均为 Xavier 初始化
+    pub fn cross_with_rng<R: Rng + ?Sized>(
+        query_dim: usize,
+        kv_dim: usize,
+        num_heads: usize,
+        num_kv_heads: usize,
+        rng: &mut R,
+    ) -> Self {
+        assert!(
+            num_kv_heads > 0 && num_heads.is_multiple_of(num_kv_heads),
+            "num_heads ({}) must be a multiple of num_kv_heads ({})",
+            num_heads,
+            num_kv_heads
+        );
+        let head_dim = query_dim / num_heads;
+        let kv_proj_dim = num_kv_heads * head_dim;
+        let init = Initializer::Xavier;
 
         MultiHeadAttention {
             num_heads,
+            num_kv_heads,
             head_dim,
-            wq: Array2::random((embed_dim, embed_dim), dist),
-            wk: Array2::random((embed_dim, embed_dim), dist),
-            wv: Array2::random((embed_dim, embed_dim), dist),
-            wo: Array2::random((embed_dim, embed_dim), dist),
+            wq: init.linear(query_dim, num_heads * head_dim, rng),
+            wk: init.linear(kv_dim, kv_proj_dim, rng),
+            wv: init.linear(kv_dim, kv_proj_dim, rng),
+            wo: init.linear(num_heads * head_dim, query_dim, rng),
+            rope: None,
+        }
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut Array2<f32>> {
+        vec![&mut self.wq, &mut self.wk, &mut self.wv, &mut self.wo]
+    }
+}
+
+impl<S: Data<Elem = f32> + Sync> MultiHeadAttention<S> {
+    // 第 h 个 Q head 对应的 K/V head
+    fn kv_head(&self, h: usize) -> usize {
+        h / (self.num_heads / self.num_kv_heads)
+    }
+
+    // Q 投影 (seq_len, num_heads * head_dim), K/V 投影 (seq_len, num_kv_heads * head_dim),
+    // 给出 rope 时对 Q/K 按 head 旋转
+    fn project_qkv(&self, x: &Array2<f32>, rope: Option<&RopeCache>) -> (Array2<f32>, Array2<f32>, Array2<f32>) {
+        let mut q = x.dot(&self.wq);
+        let mut k = x.dot(&self.wk);
+        let v = x.dot(&self.wv);
+
+        if let Some(rope) = rope {
+            assert_eq!(rope.config.dim, self.head_dim, "RopeCache dim must equal head_dim");
+            rope.apply_heads_simd(&mut q, self.num_heads);
+            rope.apply_heads_simd(&mut k, self.num_kv_heads);
         }
+        (q, k, v)
     }
 
     pub fn forward(&self, x: &Array2<f32>) -> Array2<f32> {
+        self.forward_with_rope(x, self.rope.as_deref())
+    }
+
+    // 用给定的 rope 表代替 self.rope, 用于网格随输入变化的情况
+    pub fn forward_with_rope(&self, x: &Array2<f32>, rope: Option<&RopeCache>) -> Array2<f32> {
+        let (q, k, v) = self.project_qkv(x, rope);
+        self.attend(q, k, v, None)
+    }
+
+    // 交叉注意力, query (q_len, query_dim) 与 key_value (kv_len, kv_dim) 的长度和维度可以不同;
+    // mask (q_len, kv_len) 为 false 的位置被屏蔽。两侧位置不对齐, 不施加 rope
+    pub fn forward_cross(
+        &self,
+        query: &Array2<f32>,
+        key_value: &Array2<f32>,
+        mask: Option<&Array2<bool>>,
+    ) -> Array2<f32> {
+        if let Some(mask) = mask {
+            assert_eq!(
+                mask.dim(),
+                (query.shape()[0], key_value.shape()[0]),
+                "mask must be (query_len, kv_len)"
+            );
+        }
+        let q = query.dot(&self.wq);
+        let k = key_value.dot(&self.wk);
+        let v = key_value.dot(&self.wv);
+        self.attend(q, k, v, mask)
+    }
+
+    // 打包的自注意力: x 为多个序列按行拼接, cu_seqlens = [0, len0, len0 + len1, ..., total] (见 packing::pack),
+    // 每个序列只关注自身, 结果与 block_diagonal_mask 相同但只计算对角块;
+    // rope 应为按段拼接的表 (RopeCache::packed), 使每段的位置从 0 开始
+    pub fn forward_varlen(&self, x: &Array2<f32>, cu_seqlens: &[usize], rope: Option<&RopeCache>) -> Array2<f32> {
+        assert_eq!(cu_seqlens.first(), Some(&0), "cu_seqlens must start at 0");
+        assert_eq!(cu_seqlens.last(), Some(&x.shape()[0]), "cu_seqlens must end at the packed length");
+        let (q, k, v) = self.project_qkv(x, rope);
+        self.attend_heads(q, k, v, |q, k, v, mut out| {
+            for w in cu_seqlens.windows(2) {
+                let rows = s![w[0]..w[1], ..];
+                attention_into(q.slice(rows), k.slice(rows), v.slice(rows), None, out.slice_mut(rows));
+            }
+        })
+    }
+
+    fn attend(&self, q: Array2<f32>, k: Array2<f32>, v: Array2<f32>, mask: Option<&Array2<bool>>) -> Array2<f32> {
+        self.attend_heads(q, k, v, |q, k, v, out| attention_into(q, k, v, mask, out))
+    }
+
+    // 各 head 并行计算, Q/K/V 先整体转成 (heads, seq_len, head_dim) 的连续布局,
+    // 每个 head 的结果由 kernel(q_h, k_h, v_h, out) 直接写入输出矩阵对应的列块;
+    // GQA 下多个 Q head 读取同一组 K/V, 不复制
+    fn attend_heads<F>(&self, q: Array2<f32>, k: Array2<f32>, v: Array2<f32>, kernel: F) -> Array2<f32>
+    where
+        F: Fn(ArrayView2<f32>, ArrayView2<f32>, ArrayView2<f32>, ArrayViewMut2<f32>) + Sync,
+    {
+        let seq_len = q.shape()[0];
+        let (num_heads, head_dim) = (self.num_heads, self.head_dim);
+
+        let q = split_heads(q, num_heads, head_dim);
+        let k = split_heads(k, self.num_kv_heads, head_dim);
+        let v = split_heads(v, self.num_kv_heads, head_dim);
+
+        let mut concat = Array2::<f32>::zeros((seq_len, num_heads * head_dim));
+        let out_blocks: Vec<_> = concat.axis_chunks_iter_mut(Axis(1), head_dim).collect();
+        out_blocks.into_par_iter().enumerate().for_each(|(h, out)| {
+            let kv = self.kv_head(h);
+            kernel(q.index_axis(Axis(0), h), k.index_axis(Axis(0), kv), v.index_axis(Axis(0), kv), out);
+        });
+
+        // 输出线性层
+        concat.dot(&self.wo)
+    }
+
+    // 训练用前向 (自注意力), 缓存各 head 的注意力权重
+    pub fn forward_train(&self, x: &Array2<f32>) -> (Array2<f32>, AttentionCache) {
+        let seq_len = x.shape()[0];
+        let head_dim = self.head_dim;
+        let scale = 1.0 / (head_dim as f32).sqrt();
+        let (q, k, v) = self.project_qkv(x, self.rope.as_deref());
+
+        let mut concat = Array2::<f32>::zeros((seq_len, self.num_heads * head_dim));
+        let probs: Vec<Array2<f32>> = (0..self.num_heads)
+            .into_par_iter()
+            .map(|h| {
+                let kv = self.kv_head(h);
+                let q_h = q.slice(s![.., h * head_dim..(h + 1) * head_dim]);
+                let k_h = k.slice(s![.., kv * head_dim..(kv + 1) * head_dim]);
+                // 与推理相同的数值稳定 softmax, backward 直接使用这里缓存的概率
+                let mut p = q_h.dot(&k_h.t()) * scale;
+                softmax_rows(&mut p, None);
+                p
+            })
+            .collect();
+        for (h, p) in probs.iter().enumerate() {
+            let kv = self.kv_head(h);
+            let v_h = v.slice(s![.., kv * head_dim..(kv + 1) * head_dim]);
+            concat.slice_mut(s![.., h * head_dim..(h + 1) * head_dim]).assign(&p.dot(&v_h));
+        }
+
+        let out = concat.dot(&self.wo);
+        (out, AttentionCache { x: x.clone(), q, k, v, probs, concat })
+    }
+
+    pub fn backward(&self, cache: &AttentionCache, grad_out: &Array2<f32>) -> (Array2<f32>, AttentionGrads) {
+        let head_dim = self.head_dim;
+        let scale = 1.0 / (head_dim as f32).sqrt();
+        let d_concat = grad_out.dot(&self.wo.t());
+
+        // 每个 Q head 的 (dq_h, dk_h, dv_h), GQA 下 dk/dv 累加到共享的 K/V head
+        let per_head: Vec<_> = (0..self.num_heads)
+            .into_par_iter()
+            .map(|h| {
+                let kv = self.kv_head(h);
+                let p = &cache.probs[h];
+                let q_h = cache.q.slice(s![.., h * head_dim..(h + 1) * head_dim]);
+                let k_h = cache.k.slice(s![.., kv * head_dim..(kv + 1) * head_dim]);
+                let v_h = cache.v.slice(s![.., kv * head_dim..(kv + 1) * head_dim]);
+                let d_out = d_concat.slice(s![.., h * head_dim..(h + 1) * head_dim]);
+
+                let d_v = p.t().dot(&d_out);
+                let d_p = d_out.dot(&v_h.t());
+                // softmax 反向: dS = P * (dP - sum(dP * P))
+                let mut d_s = &d_p * p;
+                for (mut row, p_row) in d_s.outer_iter_mut().zip(p.outer_iter()) {
+                    let dot = row.sum();
+                    row.scaled_add(-dot, &p_row);
+                }
+                d_s *= scale;
+                (d_s.dot(&k_h), d_s.t().dot(&q_h), d_v)
+            })
+            .collect();
+
+        let mut d_q = Array2::<f32>::zeros(cache.q.raw_dim());
+        let mut d_k = Array2::<f32>::zeros(cache.k.raw_dim());
+        let mut d_v = Array2::<f32>::zeros(cache.v.raw_dim());
+        for (h, (dq_h, dk_h, dv_h)) in per_head.into_iter().enumerate() {
+            let kv = self.kv_head(h);
+            d_q.slice_mut(s![.., h * head_dim..(h + 1) * head_dim]).assign(&dq_h);
+            let mut dk = d_k.slice_mut(s![.., kv * head_dim..(kv + 1) * head_dim]);
+            dk += &dk_h;
+            let mut dv = d_v.slice_mut(s![.., kv * head_dim..(kv + 1) * head_dim]);
+            dv += &dv_h;
+        }
+
+        if let Some(rope) = &self.rope {
+            rope.apply_heads_transposed(&mut d_q, self.num_heads);
+            rope.apply_heads_transposed(&mut d_k, self.num_kv_heads);
+        }
+
+        let x = &cache.x;
+        let grads = AttentionGrads {
+            wq: x.t().dot(&d_q),
+            wk: x.t().dot(&d_k),
+            wv: x.t().dot(&d_v),
+            wo: cache.concat.t().dot(grad_out),
+        };
+        let d_x = d_q.dot(&self.wq.t()) + d_k.dot(&self.wk.t()) + d_v.dot(&self.wv.t());
+        (d_x, grads)
+    }
+
+    // 原始的逐 head 串行实现, 保留用于对比和基准测试
+    pub fn forward_sequential(&self, x: &Array2<f32>) -> Array2<f32> {
         // x (seq_len, embed_dim)
         let seq_len = x.shape()[0];
         let embed_dim = x.shape()[1];
@@ -96,21 +439,19 @@ impl MultiHeadAttention {
         let head_dim = self.head_dim;
 
         // QKV shape(seq_len, embed_dim)
-        let q = x.dot(&self.wq);
-        let k = x.dot(&self.wk);
-        let v = x.dot(&self.wv);
+        let (q, k, v) = self.project_qkv(x, self.rope.as_deref());
 
         // 按head分割 重新reshape (num_heads, seq_len, head_dim)
         let q = q.into_shape((seq_len, num_heads, head_dim)).unwrap();
-        let k = k.into_shape((seq_len, num_heads, head_dim)).unwrap();
-        let v = v.into_shape((seq_len, num_heads, head_dim)).unwrap();
+        let k = k.into_shape((seq_len, self.num_kv_heads, head_dim)).unwrap();
+        let v = v.into_shape((seq_len, self.num_kv_heads, head_dim)).unwrap();
 
         // 每个头计算attention
         let mut heads_out = Vec::with_capacity(num_heads);
         for head_idx in 0..num_heads {
             let q_head = q.slice(s![.., head_idx, ..]).to_owned();
-            let k_head = k.slice(s![.., head_idx, ..]).to_owned();
-            let v_head = v.slice(s![.., head_idx, ..]).to_owned();
+            let k_head = k.slice(s![.., self.kv_head(head_idx), ..]).to_owned();
+            let v_head = v.slice(s![.., self.kv_head(head_idx), ..]).to_owned();
 
             let attn_out = scaled_dot_product_attention(&q_head, &k_head, &v_head);
             heads_out.push(attn_out);
@@ -129,62 +470,174 @@ impl MultiHeadAttention {
     }
 }
 
+pub struct AttentionCache {
+    x: Array2<f32>,
+    q: Array2<f32>, // rope 之后
+    k: Array2<f32>,
+    v: Array2<f32>,
+    probs: Vec<Array2<f32>>, // 每个 head (seq_len, seq_len)
+    concat: Array2<f32>,
+}
+
+pub struct AttentionGrads {
+    pub wq: Array2<f32>,
+    pub wk: Array2<f32>,
+    pub wv: Array2<f32>,
+    pub wo: Array2<f32>,
+}
+
+impl AttentionGrads {
+    // 与 MultiHeadAttention::parameters_mut 顺序一致
+    pub fn tensors(&self) -> Vec<&Array2<f32>> {
+        vec![&self.wq, &self.wk, &self.wv, &self.wo]
+    }
+}
+
 // 前馈网络
-pub struct FeedForward {
-    pub w1: Array2<f32>, // (embed_dim, ff_dim)
-    pub w2: Array2<f32>, // (ff_dim, embed_dim)
-    pub b1: Array2<f32>, // (1, ff_dim)
-    pub b2: Array2<f32>, // (1, embed_dim)
+pub struct FeedForward<S: Data<Elem = f32> = OwnedRepr<f32>> {
+    pub w1: ArrayBase<S, Ix2>, // (embed_dim, ff_dim)
+    pub w2: ArrayBase<S, Ix2>, // (ff_dim, embed_dim)
+    pub b1: ArrayBase<S, Ix2>, // (1, ff_dim)
+    pub b2: ArrayBase<S, Ix2>, // (1, embed_dim)
 }
 
 impl FeedForward {
     pub fn new(embed_dim: usize, ff_dim: usize) -> Self {
-        let dist = Uniform::new(-0.1, 0.1);
+        Self::new_with_rng(embed_dim, ff_dim, &mut rand::thread_rng())
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(embed_dim: usize, ff_dim: usize, rng: &mut R) -> Self {
         FeedForward {
-            w1: Array2::random((embed_dim, ff_dim), dist),
-            w2: Array2::random((ff_dim, embed_dim), dist),
+            w1: Initializer::Xavier.linear(embed_dim, ff_dim, rng),
+            w2: Initializer::Xavier.linear(ff_dim, embed_dim, rng),
             b1: Array2::zeros((1, ff_dim)),
             b2: Array2::zeros((1, embed_dim)),
         }
     }
 
+    pub fn parameters_mut(&mut self) -> Vec<&mut Array2<f32>> {
+        vec![&mut self.w1, &mut self.w2, &mut self.b1, &mut self.b2]
+    }
+}
+
+impl<S: Data<Elem = f32> + Sync> FeedForward<S> {
     pub fn forward(&self, x: &Array2<f32>) -> Array2<f32> {
         let hidden = gelu_array(&(x.dot(&self.w1) + &self.b1));
         hidden.dot(&self.w2) + &self.b2
     }
+
+    pub fn forward_train(&self, x: &Array2<f32>) -> (Array2<f32>, FeedForwardCache) {
+        let pre = x.dot(&self.w1) + &self.b1;
+        let hidden = gelu_array(&pre);
+        let out = hidden.dot(&self.w2) + &self.b2;
+        (out, FeedForwardCache { x: x.clone(), pre, hidden })
+    }
+
+    pub fn backward(&self, cache: &FeedForwardCache, grad_out: &Array2<f32>) -> (Array2<f32>, FeedForwardGrads) {
+        let d_hidden = grad_out.dot(&self.w2.t());
+        let d_pre = d_hidden * &cache.pre.mapv(gelu_grad);
+        let grads = FeedForwardGrads {
+            w1: cache.x.t().dot(&d_pre),
+            w2: cache.hidden.t().dot(grad_out),
+            b1: d_pre.sum_axis(Axis(0)).insert_axis(Axis(0)),
+            b2: grad_out.sum_axis(Axis(0)).insert_axis(Axis(0)),
+        };
+        (d_pre.dot(&self.w1.t()), grads)
+    }
+}
+
+pub struct FeedForwardCache {
+    x: Array2<f32>,
+    pre: Array2<f32>,
+    hidden: Array2<f32>,
+}
+
+pub struct FeedForwardGrads {
+    pub w1: Array2<f32>,
+    pub w2: Array2<f32>,
+    pub b1: Array2<f32>,
+    pub b2: Array2<f32>,
+}
+
+impl FeedForwardGrads {
+    // 与 FeedForward::parameters_mut 顺序一致
+    pub fn tensors(&self) -> Vec<&Array2<f32>> {
+        vec![&self.w1, &self.w2, &self.b1, &self.b2]
+    }
 }
 
 // Transformer 层
-pub struct TransformerLayer {
+pub struct TransformerLayer<S: Data<Elem = f32> = OwnedRepr<f32>> {
     pub embed_dim: usize,
     pub ff_dim: usize,
     pub num_heads: usize,
-    pub ln1: LayerNorm,
-    pub ln2: LayerNorm,
-    pub mha: MultiHeadAttention,
-    pub ffn: FeedForward,
+    pub ln1: LayerNorm<S>,
+    pub ln2: LayerNorm<S>,
+    pub mha: MultiHeadAttention<S>,
+    pub ffn: FeedForward<S>,
 }
 
 impl TransformerLayer {
     pub fn new(embed_dim: usize, ff_dim: usize, num_heads: usize) -> Self {
+        Self::new_with_rng(embed_dim, ff_dim, num_heads, num_heads, &mut rand::thread_rng())
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(
+        embed_dim: usize,
+        ff_dim: usize,
+        num_heads: usize,
+        num_kv_heads: usize,
+        rng: &mut R,
+    ) -> Self {
         TransformerLayer {
             embed_dim,
             ff_dim,
             num_heads,
             ln1: LayerNorm::new(embed_dim),
             ln2: LayerNorm::new(embed_dim),
-            mha: MultiHeadAttention::new(embed_dim, num_heads),
-            ffn: FeedForward::new(embed_dim, ff_dim),
+            mha: MultiHeadAttention::new_with_rng(embed_dim, num_heads, num_kv_heads, rng),
+            ffn: FeedForward::new_with_rng(embed_dim, ff_dim, rng),
         }
     }
+}
 
+impl<S: Data<Elem = f32> + Sync> TransformerLayer<S> {
     pub fn forward(&self, x: &Array2<f32>) -> Array2<f32> {
+        self.forward_with_rope(x, self.mha.rope.as_deref())
+    }
+
+    pub fn forward_with_rope(&self, x: &Array2<f32>, rope: Option<&RopeCache>) -> Array2<f32> {
         let x_norm = self.ln1.forward(x);
-        let attn_out = self.mha.forward(&x_norm);
+        let attn_out = self.mha.forward_with_rope(&x_norm, rope);
         let x = x + attn_out; 
 
         let x_norm = self.ln2.forward(&x);
         let ffn_out = self.ffn.forward(&x_norm);
         x + ffn_out 
     }
+
+    // 打包序列, 见 MultiHeadAttention::forward_varlen; LayerNorm 和 FFN 逐行计算, 不受打包影响
+    pub fn forward_varlen(&self, x: &Array2<f32>, cu_seqlens: &[usize], rope: Option<&RopeCache>) -> Array2<f32> {
+        let x = x + self.mha.forward_varlen(&self.ln1.forward(x), cu_seqlens, rope);
+        let ffn_out = self.ffn.forward(&self.ln2.forward(&x));
+        x + ffn_out
+    }
+
+    // 与 forward 相同, 额外记录 {name}.ln1/.attn/.residual1/.ln2/.ffn 和 {name}
+    pub fn forward_captured(&self, x: &Array2<f32>, name: &str, capture: &mut ActivationCapture) -> Array2<f32> {
+        let x_norm = self.ln1.forward(x);
+        capture.record(&format!("{}.ln1", name), &x_norm);
+        let attn_out = self.mha.forward(&x_norm);
+        capture.record(&format!("{}.attn", name), &attn_out);
+        let x = x + attn_out;
+        capture.record(&format!("{}.residual1", name), &x);
+
+        let x_norm = self.ln2.forward(&x);
+        capture.record(&format!("{}.ln2", name), &x_norm);
+        let ffn_out = self.ffn.forward(&x_norm);
+        capture.record(&format!("{}.ffn", name), &ffn_out);
+        let out = x + ffn_out;
+        capture.record(name, &out);
+        out
+    }
 }
@@ -0,0 +1,156 @@
+// src/video.rs
+//
+// 视频输入: 从图片目录或动图 (GIF / APNG) 读出帧序列, 均匀或按场景变化抽取 N 帧,
+// 逐帧预处理并批量编码, 之后可选地做时间维池化或叠加时间位置编码
+//
+//   let frames = load_frames("clip.gif")?;
+//   let clip = processor.preprocess_video(&frames, 8, FrameSampling::Uniform);
+//   let tokens = encoder.forward_video(&clip.frames, Temporal::MeanPool { window: 2 });
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::{AnimationDecoder, DynamicImage, ImageResult};
+use ndarray::{Array2, Array3, Axis};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use crate::processor::ImageProcessor;
+
+const FRAME_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "bmp", "webp", "tiff"];
+
+/// 读取帧序列: 目录按文件名排序读取其中的图片, `.gif` / `.png` 解码全部动画帧 (普通 PNG 为单帧),
+/// 其他文件按单张图片读取
+pub fn load_frames<P: AsRef<Path>>(path: P) -> ImageResult<Vec<DynamicImage>> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| has_extension(p, &FRAME_EXTENSIONS))
+            .collect();
+        files.sort();
+        return files.par_iter().map(image::open).collect();
+    }
+
+    if has_extension(path, &["gif"]) {
+        let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+        return collect_frames(decoder);
+    }
+    if has_extension(path, &["png", "apng"]) {
+        let decoder = PngDecoder::new(BufReader::new(File::open(path)?))?;
+        if decoder.is_apng() {
+            return collect_frames(decoder.apng());
+        }
+    }
+    Ok(vec![image::open(path)?])
+}
+
+fn collect_frames<'a>(decoder: impl AnimationDecoder<'a>) -> ImageResult<Vec<DynamicImage>> {
+    let frames = decoder.into_frames().collect_frames()?;
+    Ok(frames.into_iter().map(|f| DynamicImage::ImageRgba8(f.into_buffer())).collect())
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameSampling {
+    /// 把序列等分为 N 段, 各取中间一帧
+    Uniform,
+    /// 第一帧加上与前一帧差异最大的 N - 1 帧 (按 32x32 灰度缩略图的平均绝对差), 按时间顺序返回
+    SceneChange,
+}
+
+/// 从 frames 中选出至多 n 帧的下标, 升序
+pub fn sample_frames(frames: &[DynamicImage], n: usize, sampling: FrameSampling) -> Vec<usize> {
+    let len = frames.len();
+    if n >= len {
+        return (0..len).collect();
+    }
+    match sampling {
+        FrameSampling::Uniform => (0..n).map(|i| ((2 * i + 1) * len) / (2 * n)).collect(),
+        FrameSampling::SceneChange => {
+            if n == 0 {
+                return Vec::new();
+            }
+            let thumbs: Vec<_> = frames
+                .par_iter()
+                .map(|f| f.resize_exact(32, 32, image::imageops::Triangle).to_luma8())
+                .collect();
+            let mut changes: Vec<(usize, f32)> = (1..len)
+                .map(|i| {
+                    let (cur, prev) = (thumbs[i].as_raw(), thumbs[i - 1].as_raw());
+                    let diff: u32 = cur.iter().zip(prev).map(|(&a, &b)| a.abs_diff(b) as u32).sum();
+                    (i, diff as f32 / cur.len() as f32)
+                })
+                .collect();
+            // 差异相同时取较早的帧
+            changes.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+            let mut picked: Vec<usize> =
+                std::iter::once(0).chain(changes.iter().take(n - 1).map(|&(i, _)| i)).collect();
+            picked.sort_unstable();
+            picked
+        }
+    }
+}
+
+pub struct VideoClip {
+    /// 选中的帧在原序列中的下标
+    pub frame_indices: Vec<usize>,
+    /// 预处理后的各帧 (3, S, S)
+    pub frames: Vec<Array3<f32>>,
+}
+
+impl ImageProcessor {
+    /// 抽取至多 n 帧并并行预处理
+    pub fn preprocess_video(&self, frames: &[DynamicImage], n: usize, sampling: FrameSampling) -> VideoClip {
+        let frame_indices = sample_frames(frames, n, sampling);
+        let frames = frame_indices.par_iter().map(|&i| self.preprocess(&frames[i])).collect();
+        VideoClip { frame_indices, frames }
+    }
+}
+
+/// 逐帧编码输出上的时间维处理
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Temporal {
+    None,
+    /// 每 window 个相邻帧的 token 取平均, 帧数不整除时最后一组较短
+    MeanPool { window: usize },
+    /// 给第 t 帧的所有 token 加上第 t 个正弦位置编码
+    PositionEmbedding,
+}
+
+pub fn apply_temporal(mut outputs: Vec<Array2<f32>>, temporal: Temporal) -> Vec<Array2<f32>> {
+    match temporal {
+        Temporal::None => outputs,
+        Temporal::MeanPool { window } => outputs
+            .chunks(window.max(1))
+            .map(|group| {
+                let views: Vec<_> = group.iter().map(|x| x.view()).collect();
+                let stacked = ndarray::stack(Axis(0), &views).expect("frames must have the same token shape");
+                stacked.mean_axis(Axis(0)).unwrap()
+            })
+            .collect(),
+        Temporal::PositionEmbedding => {
+            if let Some(first) = outputs.first() {
+                let table = temporal_position_embedding(outputs.len(), first.shape()[1]);
+                for (out, pe) in outputs.iter_mut().zip(table.outer_iter()) {
+                    *out += &pe;
+                }
+            }
+            outputs
+        }
+    }
+}
+
+/// (num_frames, dim) 的正弦位置编码 (Transformer 原文的 sin/cos 交错形式)
+pub fn temporal_position_embedding(num_frames: usize, dim: usize) -> Array2<f32> {
+    Array2::from_shape_fn((num_frames, dim), |(t, j)| {
+        let freq = 1.0 / 10000f32.powf((j / 2 * 2) as f32 / dim as f32);
+        let angle = t as f32 * freq;
+        if j % 2 == 0 { angle.sin() } else { angle.cos() }
+    })
+}
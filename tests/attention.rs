@@ -0,0 +1,113 @@
+// tests/attention.rs
+
+use cogvlm_image_preprocessor::rope::{RopeCache, RopeConfig};
+use cogvlm_image_preprocessor::transformer::MultiHeadAttention;
+use ndarray::Array2;
+use std::sync::Arc;
+
+fn input(seq_len: usize, dim: usize) -> Array2<f32> {
+    Array2::from_shape_fn((seq_len, dim), |(i, j)| ((i * dim + j) as f32 * 0.13).sin())
+}
+
+fn max_abs_diff(a: &Array2<f32>, b: &Array2<f32>) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max)
+}
+
+#[test]
+fn parallel_heads_match_sequential() {
+    let mut mha = MultiHeadAttention::new(48, 6);
+    let x = input(19, 48);
+    assert!(max_abs_diff(&mha.forward(&x), &mha.forward_sequential(&x)) < 1e-5);
+
+    mha.rope = Some(Arc::new(RopeCache::from_config(19, RopeConfig::new(8))));
+    assert!(max_abs_diff(&mha.forward(&x), &mha.forward_sequential(&x)) < 1e-5);
+}
+
+// GQA 等价于把每组 K/V 投影复制给组内所有 Q head 的普通多头注意力
+#[test]
+fn grouped_kv_heads_match_expanded_weights() {
+    let (dim, heads, kv_heads, head_dim) = (48, 6, 2, 8);
+    let group = heads / kv_heads;
+    let mut gqa = MultiHeadAttention::with_kv_heads(dim, heads, kv_heads);
+    gqa.rope = Some(Arc::new(RopeCache::from_config(11, RopeConfig::new(head_dim))));
+    assert_eq!(gqa.wk.dim(), (dim, kv_heads * head_dim));
+
+    let expand = |w: &Array2<f32>| {
+        Array2::from_shape_fn((dim, dim), |(i, j)| w[[i, (j / head_dim / group) * head_dim + j % head_dim]])
+    };
+    let mut mha = MultiHeadAttention::new(dim, heads);
+    mha.wq = gqa.wq.clone();
+    mha.wk = expand(&gqa.wk);
+    mha.wv = expand(&gqa.wv);
+    mha.wo = gqa.wo.clone();
+    mha.rope = gqa.rope.clone();
+
+    let x = input(11, dim);
+    let expected = mha.forward(&x);
+    assert!(max_abs_diff(&gqa.forward(&x), &expected) < 1e-5);
+    assert!(max_abs_diff(&gqa.forward_sequential(&x), &expected) < 1e-5);
+
+    let mqa = MultiHeadAttention::with_kv_heads(dim, heads, 1);
+    assert!(max_abs_diff(&mqa.forward(&x), &mqa.forward_sequential(&x)) < 1e-5);
+}
+
+#[test]
+fn cross_attention_handles_different_lengths_and_dims() {
+    let mha = MultiHeadAttention::cross(32, 20, 4, 2);
+    let query = input(5, 32);
+    let key_value = input(9, 20);
+    assert_eq!(mha.forward_cross(&query, &key_value, None).dim(), (5, 32));
+
+    // 屏蔽后半部分 key 等价于只传入前半部分
+    let mask = Array2::from_shape_fn((5, 9), |(_, j)| j < 4);
+    let masked = mha.forward_cross(&query, &key_value, Some(&mask));
+    let truncated = mha.forward_cross(&query, &key_value.slice(ndarray::s![..4, ..]).to_owned(), None);
+    assert!(max_abs_diff(&masked, &truncated) < 1e-5);
+
+    // 整行屏蔽的 query 输出为 0
+    let none = Array2::from_elem((5, 9), false);
+    assert!(mha.forward_cross(&query, &key_value, Some(&none)).iter().all(|&v| v == 0.0));
+}
+
+// 自注意力是 query == key_value 且无 rope 时的交叉注意力
+#[test]
+fn cross_attention_on_same_sequence_matches_self_attention() {
+    let mha = MultiHeadAttention::with_kv_heads(24, 6, 3);
+    let x = input(7, 24);
+    assert!(max_abs_diff(&mha.forward_cross(&x, &x, None), &mha.forward(&x)) < 1e-6);
+}
+
+// 参考实现: f64 中减去行最大值的 softmax
+fn reference_attention(mha: &MultiHeadAttention, x: &Array2<f32>) -> Array2<f32> {
+    let to64 = |a: &Array2<f32>| a.mapv(|v| v as f64);
+    let (x, hd) = (to64(x), mha.head_dim);
+    let (q, k, v) = (x.dot(&to64(&mha.wq)), x.dot(&to64(&mha.wk)), x.dot(&to64(&mha.wv)));
+    let mut concat = Array2::<f64>::zeros((x.nrows(), mha.num_heads * hd));
+    for h in 0..mha.num_heads {
+        let cols = ndarray::s![.., h * hd..(h + 1) * hd];
+        let mut scores = q.slice(cols).dot(&k.slice(cols).t()) / (hd as f64).sqrt();
+        for mut row in scores.outer_iter_mut() {
+            let max = row.fold(f64::NEG_INFINITY, |m, &s| m.max(s));
+            row.mapv_inplace(|s| (s - max).exp());
+            let sum = row.sum();
+            row /= sum;
+        }
+        concat.slice_mut(cols).assign(&scores.dot(&v.slice(cols)));
+    }
+    concat.dot(&to64(&mha.wo)).mapv(|v| v as f32)
+}
+
+#[test]
+fn large_logits_do_not_overflow_softmax() {
+    let mut mha = MultiHeadAttention::new(32, 4);
+    mha.wq *= 30.0;
+    mha.wk *= 30.0;
+    let x = input(9, 32);
+    let expected = reference_attention(&mha, &x);
+    assert!(expected.iter().all(|v| v.is_finite()));
+
+    for out in [mha.forward(&x), mha.forward_sequential(&x), mha.forward_cross(&x, &x, None)] {
+        assert!(out.iter().all(|v| v.is_finite()));
+        assert!(max_abs_diff(&out, &expected) < 1e-3);
+    }
+}
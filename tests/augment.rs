@@ -0,0 +1,104 @@
+// tests/augment.rs
+
+use cogvlm_image_preprocessor::augment::{Augmentation, RandOp, Transform};
+use cogvlm_image_preprocessor::processor::{process_images_in_batch, ImageProcessor};
+use image::{DynamicImage, RgbImage};
+use ndarray::{s, Array3, Axis};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+fn tensor() -> Array3<f32> {
+    Array3::from_shape_fn((3, 12, 16), |(c, y, x)| ((c * 7 + y * 3 + x) as f32 * 0.13).sin() * 0.5 + 0.5)
+}
+
+fn images(n: u8) -> Vec<DynamicImage> {
+    (0..n)
+        .map(|i| DynamicImage::ImageRgb8(RgbImage::from_fn(40, 30, |x, y| image::Rgb([x as u8 * 6, y as u8 * 8, i * 40]))))
+        .collect()
+}
+
+fn in_unit_range(x: &Array3<f32>) -> bool {
+    x.iter().all(|&v| (0.0..=1.0).contains(&v))
+}
+
+#[test]
+fn batch_augmentation_is_seeded_per_image() {
+    let processor = ImageProcessor::new(32).with_augmentation(Augmentation::standard(7));
+    let a = process_images_in_batch(images(6), &processor);
+    let b = process_images_in_batch(images(6), &processor);
+    assert_eq!(a.image, b.image);
+    assert_eq!(a.image.index_axis(Axis(0), 3), processor.preprocess_item(&images(6)[3], 3));
+
+    // 相同输入在不同下标或不同种子下得到不同的增强
+    let same = vec![images(1).remove(0); 2];
+    let out = process_images_in_batch(same.clone(), &processor);
+    assert_ne!(out.image.index_axis(Axis(0), 0), out.image.index_axis(Axis(0), 1));
+    let other = process_images_in_batch(same, &ImageProcessor::new(32).with_augmentation(Augmentation::standard(8)));
+    assert_ne!(out.image.index_axis(Axis(0), 0), other.image.index_axis(Axis(0), 0));
+}
+
+#[test]
+fn empty_augmentation_matches_plain_preprocess() {
+    let img = &images(1)[0];
+    let plain = ImageProcessor::new(32).preprocess(img);
+    assert_eq!(ImageProcessor::new(32).with_augmentation(Augmentation::new(1)).preprocess_item(img, 3), plain);
+    // 单图接口不增强
+    assert_eq!(ImageProcessor::new(32).with_augmentation(Augmentation::standard(1)).preprocess(img), plain);
+}
+
+#[test]
+fn geometric_transforms() {
+    let x = tensor();
+    let mut rng = StdRng::seed_from_u64(0);
+
+    let mut flipped = x.clone();
+    Transform::HorizontalFlip { p: 1.0 }.apply(&mut flipped, &mut rng);
+    assert_eq!(flipped, x.slice(s![.., .., ..;-1]));
+
+    // 覆盖整图的裁剪不改变图像
+    let mut cropped = x.clone();
+    Transform::RandomResizedCrop { scale: (1.0, 1.0), ratio: (16.0 / 12.0, 16.0 / 12.0) }.apply(&mut cropped, &mut rng);
+    assert!(cropped.iter().zip(x.iter()).all(|(a, b)| (a - b).abs() < 1e-6));
+
+    let mut cropped = x.clone();
+    Transform::RandomResizedCrop { scale: (0.2, 0.5), ratio: (0.75, 1.33) }.apply(&mut cropped, &mut rng);
+    assert_eq!(cropped.dim(), x.dim());
+    assert!(in_unit_range(&cropped));
+}
+
+#[test]
+fn color_jitter_and_rand_augment_stay_in_range() {
+    let x = tensor();
+    let mut rng = StdRng::seed_from_u64(1);
+
+    let mut unchanged = x.clone();
+    Transform::ColorJitter { brightness: 0.0, contrast: 0.0, saturation: 0.0 }.apply(&mut unchanged, &mut rng);
+    assert_eq!(unchanged, x);
+
+    for _ in 0..20 {
+        let mut y = x.clone();
+        Transform::ColorJitter { brightness: 0.8, contrast: 0.8, saturation: 0.8 }.apply(&mut y, &mut rng);
+        Transform::RandAugment { num_ops: 3, magnitude: 0.9 }.apply(&mut y, &mut rng);
+        assert_eq!(y.dim(), x.dim());
+        assert!(in_unit_range(&y));
+    }
+
+    let mut shifted = x.clone();
+    RandOp::TranslateY.apply(&mut shifted, 0.0, &mut rng);
+    assert_eq!(shifted, x);
+}
+
+#[test]
+fn random_erasing_overwrites_one_region() {
+    let x = Array3::from_elem((3, 20, 20), 2.0);
+    let mut rng = StdRng::seed_from_u64(2);
+
+    let mut kept = x.clone();
+    Transform::RandomErasing { p: 0.0, scale: (0.1, 0.3), ratio: (0.5, 2.0) }.apply(&mut kept, &mut rng);
+    assert_eq!(kept, x);
+
+    let mut erased = x.clone();
+    Transform::RandomErasing { p: 1.0, scale: (0.1, 0.3), ratio: (0.5, 2.0) }.apply(&mut erased, &mut rng);
+    let changed = erased.iter().filter(|&&v| v != 2.0).count() as f32 / erased.len() as f32;
+    assert!((0.05..=0.35).contains(&changed), "erased fraction {}", changed);
+}
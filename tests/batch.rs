@@ -0,0 +1,43 @@
+// tests/batch.rs
+
+use cogvlm_image_preprocessor::processor::{process_images_in_batch, process_images_into, ImageProcessor};
+use image::{DynamicImage, RgbImage};
+use ndarray::{ArrayViewMut4, Axis};
+
+fn images(n: u8) -> Vec<DynamicImage> {
+    (0..n)
+        .map(|i| DynamicImage::ImageRgb8(RgbImage::from_fn(40 + i as u32, 30, |x, y| image::Rgb([x as u8 * 5, y as u8 * 7, i * 50]))))
+        .collect()
+}
+
+#[test]
+fn batch_is_contiguous_nchw_matching_single_preprocess() {
+    let processor = ImageProcessor::new(32);
+    let out = process_images_in_batch(images(3), &processor);
+
+    assert_eq!(out.image.dim(), (3, 3, 32, 32));
+    assert!(out.image.as_slice().is_some());
+    for (i, img) in images(3).iter().enumerate() {
+        assert_eq!(out.image.index_axis(Axis(0), i), processor.preprocess(img));
+    }
+}
+
+#[test]
+fn writes_into_caller_buffer() {
+    let processor = ImageProcessor::new(32);
+    let mut buffer = vec![f32::NAN; 2 * 3 * 32 * 32];
+    let view = ArrayViewMut4::from_shape((2, 3, 32, 32), &mut buffer).unwrap();
+    let metadata = process_images_into(&images(2), &processor, view);
+
+    assert_eq!(metadata.len(), 2);
+    let expected = process_images_in_batch(images(2), &processor);
+    assert_eq!(buffer, expected.image.into_raw_vec());
+}
+
+#[test]
+#[should_panic(expected = "output buffer must be")]
+fn rejects_mismatched_buffer() {
+    let mut buffer = vec![0.0; 3 * 3 * 16 * 16];
+    let view = ArrayViewMut4::from_shape((3, 3, 16, 16), &mut buffer).unwrap();
+    process_images_into(&images(3), &ImageProcessor::new(32), view);
+}
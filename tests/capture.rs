@@ -0,0 +1,28 @@
+// tests/capture.rs
+
+use cogvlm_image_preprocessor::capture::ActivationCapture;
+use cogvlm_image_preprocessor::transformer::TransformerLayer;
+use ndarray::Array2;
+use ndarray_npy::read_npy;
+
+#[test]
+fn captured_forward_matches_forward_and_respects_filter() {
+    let layer = TransformerLayer::new(16, 32, 2);
+    let x = Array2::from_shape_fn((5, 16), |(i, j)| ((i * 16 + j) as f32 * 0.1).sin());
+
+    let mut capture = ActivationCapture::with_filter(["layer0"]);
+    let out = layer.forward_captured(&x, "layer0", &mut capture);
+    layer.forward_captured(&x, "layer1", &mut capture);
+
+    assert_eq!(out, layer.forward(&x));
+    assert_eq!(capture.get("layer0").unwrap(), &out.clone().into_dyn());
+    assert!(capture.get("layer0.attn").is_some());
+    assert!(capture.get("layer1").is_none());
+    assert!(capture.get("layer1.ln1").is_none());
+
+    let dir = std::env::temp_dir().join(format!("cogvlm_capture_{}", std::process::id()));
+    capture.write_npy_dir(&dir).unwrap();
+    let dumped: Array2<f32> = read_npy(dir.join("layer0.npy")).unwrap();
+    assert_eq!(dumped, out);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
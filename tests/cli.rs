@@ -0,0 +1,164 @@
+// tests/cli.rs
+#![cfg(feature = "cli")]
+
+use cogvlm_image_preprocessor::encoder::{EncoderConfig, VisionEncoder};
+use cogvlm_image_preprocessor::processor::ImageProcessor;
+use cogvlm_image_preprocessor::resampler::DownsampleKind;
+use image::{DynamicImage, RgbImage};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cogvlm-cli-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// a.png 与 a.bmp 文件名相同只有扩展名不同, bad.png 无法解码
+fn write_inputs(dir: &Path) {
+    let img = RgbImage::from_fn(20, 12, |x, y| image::Rgb([x as u8 * 10, y as u8 * 20, 60]));
+    img.save(dir.join("a.png")).unwrap();
+    img.save(dir.join("a.bmp")).unwrap();
+    std::fs::write(dir.join("bad.png"), b"not an image").unwrap();
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cogvlm-vision")).args(args).output().unwrap()
+}
+
+#[test]
+fn preprocess_keeps_going_past_unreadable_files() {
+    let dir = temp_dir("preprocess");
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    std::fs::create_dir_all(&input).unwrap();
+    write_inputs(&input);
+
+    let out = run(&["preprocess", input.to_str().unwrap(), "-o", output.to_str().unwrap(), "--image-size", "16"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("1 of 3 images could not be read"));
+
+    let mut written: Vec<_> = std::fs::read_dir(&output).unwrap().map(|e| e.unwrap().file_name()).collect();
+    written.sort();
+    assert_eq!(written, ["a.bmp.npy", "a.png.npy"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn encode_writes_readable_images_and_reports_the_rest() {
+    let dir = temp_dir("encode");
+    let input = dir.join("in");
+    std::fs::create_dir_all(&input).unwrap();
+    write_inputs(&input);
+    let config = dir.join("config.json");
+    std::fs::write(
+        &config,
+        r#"{"image_size": 16, "patch_size": 8, "embed_dim": 8, "num_heads": 2, "ff_dim": 16, "num_layers": 1, "out_dim": 4, "seed": 1}"#,
+    )
+    .unwrap();
+    let output = dir.join("out.jsonl");
+
+    let out = run(&["encode", input.to_str().unwrap(), "-o", output.to_str().unwrap(), "--config", config.to_str().unwrap()]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("bad.png"));
+
+    let lines: Vec<serde_json::Value> =
+        std::fs::read_to_string(&output).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines.iter().all(|l| l["shape"] == serde_json::json!([4, 4])));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// 不同于默认配置的小模型, 层数和 downsample 都只能从权重文件头得知
+fn saved_encoder(dir: &Path) -> (VisionEncoder, PathBuf) {
+    let config = EncoderConfig {
+        image_size: 16,
+        patch_size: 4,
+        embed_dim: 8,
+        num_heads: 2,
+        ff_dim: 16,
+        num_layers: 1,
+        out_dim: 4,
+        downsample: DownsampleKind::AvgPool,
+        ..EncoderConfig::default()
+    };
+    let encoder = VisionEncoder::new(config);
+    let weights = dir.join("w.safetensors");
+    encoder.save_safetensors(&weights).unwrap();
+    (encoder, weights)
+}
+
+#[test]
+fn encode_takes_the_config_stored_with_the_weights() {
+    let dir = temp_dir("stored-config");
+    let (encoder, weights) = saved_encoder(&dir);
+    let img = RgbImage::from_fn(20, 12, |x, y| image::Rgb([x as u8 * 10, y as u8 * 20, 60]));
+    img.save(dir.join("a.png")).unwrap();
+    let (input, output) = (dir.join("a.png"), dir.join("out.jsonl"));
+    let args = ["encode", input.to_str().unwrap(), "-o", output.to_str().unwrap(), "--weights", weights.to_str().unwrap()];
+
+    let out = run(&args);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+
+    let line: serde_json::Value = serde_json::from_str(std::fs::read_to_string(&output).unwrap().trim()).unwrap();
+    let x = ImageProcessor::for_encoder(&encoder.config).preprocess(&DynamicImage::ImageRgb8(img));
+    let expected = encoder.forward(&x);
+    assert_eq!(line["shape"], serde_json::json!(expected.shape()));
+    let rows: Vec<Vec<f32>> = serde_json::from_value(line["embedding"].clone()).unwrap();
+    assert!(rows.concat().iter().zip(expected.iter()).all(|(a, e)| (a - e).abs() < 1e-5));
+
+    // --config 与文件头中的配置不一致时拒绝
+    let config = dir.join("config.json");
+    std::fs::write(&config, serde_json::to_string(&EncoderConfig { num_layers: 2, ..encoder.config.clone() }).unwrap()).unwrap();
+    let out = run(&[&args[..], &["--config", config.to_str().unwrap()]].concat());
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("does not match the config stored in the weights"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// serve 启动后在 stderr 打印 listening on ..., 读到该行或进程退出时返回 (是否在监听, stderr)
+#[cfg(feature = "server")]
+fn start_serving(args: &[&str]) -> (bool, String) {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cogvlm-vision")).args(args).stderr(Stdio::piped()).spawn().unwrap();
+    let mut stderr = String::new();
+    let mut listening = false;
+    for line in BufReader::new(child.stderr.take().unwrap()).lines() {
+        let line = line.unwrap();
+        stderr.push_str(&line);
+        if line.starts_with("listening on") {
+            listening = true;
+            break;
+        }
+    }
+    let _ = child.kill();
+    child.wait().unwrap();
+    (listening, stderr)
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn serve_mmap_takes_the_config_stored_with_the_weights() {
+    let dir = temp_dir("serve-mmap");
+    let (encoder, weights) = saved_encoder(&dir);
+    let args = ["serve", "--addr", "127.0.0.1:0", "--mmap", "--weights", weights.to_str().unwrap()];
+
+    let (listening, stderr) = start_serving(&args);
+    assert!(listening, "{}", stderr);
+
+    let config = dir.join("config.json");
+    std::fs::write(&config, serde_json::to_string(&EncoderConfig { rope_2d: true, ..encoder.config.clone() }).unwrap()).unwrap();
+    let (listening, stderr) = start_serving(&[&args[..], &["--config", config.to_str().unwrap()]].concat());
+    assert!(!listening);
+    assert!(stderr.contains("does not match the config stored in the weights"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bench_rejects_zero_iterations() {
+    let out = run(&["bench", "--iters", "0"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--iters"));
+}
@@ -0,0 +1,207 @@
+// tests/gradients.rs
+//
+// 用中心差分检查各模块 backward 的梯度, loss = sum(out * r), r 为固定的权重
+
+use cogvlm_image_preprocessor::glu_projection::GLUProjection;
+use cogvlm_image_preprocessor::optim::{AdamW, Sgd};
+use cogvlm_image_preprocessor::patch_embed::PatchEmbed;
+use cogvlm_image_preprocessor::rope::{RopeCache, RopeConfig};
+use cogvlm_image_preprocessor::transformer::{FeedForward, LayerNorm, MultiHeadAttention};
+use ndarray::{Array, Array2, Array3, Dimension, ShapeBuilder};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::sync::Arc;
+
+const EPS: f32 = 1e-2;
+
+fn pattern<D: Dimension, Sh: ShapeBuilder<Dim = D>>(shape: Sh, phase: f32) -> Array<f32, D> {
+    let mut i = 0.0;
+    Array::from_shape_fn(shape, |_| {
+        i += 1.0;
+        (i * 0.37 + phase).sin()
+    })
+}
+
+fn weighted_sum<D: Dimension>(out: &Array<f32, D>, r: &Array<f32, D>) -> f64 {
+    out.iter().zip(r.iter()).map(|(&a, &b)| a as f64 * b as f64).sum()
+}
+
+fn max_abs_diff(a: &Array2<f32>, b: &Array2<f32>) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max)
+}
+
+fn assert_close(analytic: f32, numeric: f32, what: &str) {
+    let tol = 2e-3 + 2e-2 * analytic.abs().max(numeric.abs());
+    assert!((analytic - numeric).abs() <= tol, "{}: analytic {} vs numeric {}", what, analytic, numeric);
+}
+
+// 对 x 中若干位置做中心差分, 与解析梯度比较
+fn check_input<D: Dimension>(x: &Array<f32, D>, grad: &Array<f32, D>, loss: impl Fn(&Array<f32, D>) -> f64) {
+    let n = x.len();
+    for flat in (0..n).step_by((n / 7).max(1)) {
+        let mut plus = x.clone();
+        let mut minus = x.clone();
+        plus.as_slice_mut().unwrap()[flat] += EPS;
+        minus.as_slice_mut().unwrap()[flat] -= EPS;
+        let numeric = ((loss(&plus) - loss(&minus)) / (2.0 * EPS as f64)) as f32;
+        assert_close(grad.as_slice().unwrap()[flat], numeric, &format!("input[{}]", flat));
+    }
+}
+
+// 对模块第 k 个参数的若干位置做中心差分
+fn check_params<M>(
+    module: &mut M,
+    grads: Vec<Array2<f32>>,
+    params: fn(&mut M) -> Vec<&mut Array2<f32>>,
+    loss: impl Fn(&M) -> f64,
+) {
+    for (k, grad) in grads.iter().enumerate() {
+        let n = grad.len();
+        for flat in (0..n).step_by((n / 5).max(1)) {
+            let original = params(module)[k].as_slice().unwrap()[flat];
+            params(module)[k].as_slice_mut().unwrap()[flat] = original + EPS;
+            let plus = loss(module);
+            params(module)[k].as_slice_mut().unwrap()[flat] = original - EPS;
+            let minus = loss(module);
+            params(module)[k].as_slice_mut().unwrap()[flat] = original;
+
+            let numeric = ((plus - minus) / (2.0 * EPS as f64)) as f32;
+            assert_close(grad.as_slice().unwrap()[flat], numeric, &format!("param {}[{}]", k, flat));
+        }
+    }
+}
+
+#[test]
+fn layer_norm_gradients() {
+    let mut ln = LayerNorm::new(12);
+    ln.gamma = pattern((1, 12), 1.0) + 1.0;
+    ln.beta = pattern((1, 12), 2.0);
+    let x = pattern((5, 12), 0.0) * 2.0;
+    let r = pattern((5, 12), 3.0);
+
+    let (out, cache) = ln.forward_train(&x);
+    assert!(max_abs_diff(&out, &ln.forward(&x)) < 1e-5);
+    let (dx, grads) = ln.backward(&cache, &r);
+
+    check_input(&x, &dx, |x| weighted_sum(&ln.forward(x), &r));
+    let grads = grads.tensors().into_iter().cloned().collect();
+    check_params(&mut ln, grads, LayerNorm::parameters_mut, |m| weighted_sum(&m.forward(&x), &r));
+}
+
+#[test]
+fn feed_forward_gradients() {
+    let mut ffn = FeedForward::new_with_rng(8, 16, &mut StdRng::seed_from_u64(1));
+    ffn.b1 = pattern((1, 16), 1.0) * 0.1;
+    let x = pattern((4, 8), 0.0);
+    let r = pattern((4, 8), 3.0);
+
+    let (_, cache) = ffn.forward_train(&x);
+    let (dx, grads) = ffn.backward(&cache, &r);
+
+    check_input(&x, &dx, |x| weighted_sum(&ffn.forward(x), &r));
+    let grads = grads.tensors().into_iter().cloned().collect();
+    check_params(&mut ffn, grads, FeedForward::parameters_mut, |m| weighted_sum(&m.forward(&x), &r));
+}
+
+#[test]
+fn attention_gradients_with_rope_and_grouped_kv() {
+    let mut mha = MultiHeadAttention::new_with_rng(16, 4, 2, &mut StdRng::seed_from_u64(2));
+    mha.rope = Some(Arc::new(RopeCache::from_config(6, RopeConfig::new(4))));
+    let x = pattern((6, 16), 0.0) * 2.0;
+    let r = pattern((6, 16), 3.0);
+
+    let (out, cache) = mha.forward_train(&x);
+    assert!(max_abs_diff(&out, &mha.forward(&x)) < 1e-5);
+    let (dx, grads) = mha.backward(&cache, &r);
+
+    check_input(&x, &dx, |x| weighted_sum(&mha.forward(x), &r));
+    let grads = grads.tensors().into_iter().cloned().collect();
+    check_params(&mut mha, grads, MultiHeadAttention::parameters_mut, |m| weighted_sum(&m.forward(&x), &r));
+}
+
+// logits 很大时 softmax 饱和, 前向和梯度仍应有限
+#[test]
+fn attention_gradients_stay_finite_for_large_logits() {
+    let mut mha = MultiHeadAttention::new_with_rng(16, 4, 4, &mut StdRng::seed_from_u64(3));
+    mha.wq *= 30.0;
+    mha.wk *= 30.0;
+    let x = pattern((6, 16), 0.0) * 2.0;
+    let r = pattern((6, 16), 3.0);
+
+    let (out, cache) = mha.forward_train(&x);
+    assert!(out.iter().all(|v| v.is_finite()));
+    assert!(max_abs_diff(&out, &mha.forward(&x)) < 1e-3);
+    let (dx, grads) = mha.backward(&cache, &r);
+    assert!(dx.iter().all(|v| v.is_finite()));
+    assert!(grads.tensors().iter().all(|g| g.iter().all(|v| v.is_finite())));
+}
+
+#[test]
+fn glu_gradients() {
+    let x = pattern((5, 8), 0.0);
+    let r = pattern((5, 6), 3.0);
+    // 差分跨过 ReLU 门控的折点时数值梯度无意义, 取第一个门控输入都远离 0 的种子
+    let mut glu = (0..)
+        .map(|seed| {
+            let mut glu = GLUProjection::new_with_rng(8, 6, &mut StdRng::seed_from_u64(seed));
+            glu.bias = Some(pattern((1, 12), 1.0) * 0.2);
+            glu
+        })
+        .find(|glu| {
+            let pre = x.dot(&glu.weight) + glu.bias.as_ref().unwrap();
+            pre.slice(ndarray::s![.., 6..]).iter().all(|v| v.abs() > 4.0 * EPS)
+        })
+        .unwrap();
+
+    let (_, cache) = glu.forward_train(&x);
+    let (dx, grads) = glu.backward(&cache, &r);
+
+    check_input(&x, &dx, |x| weighted_sum(&glu.forward(x), &r));
+    let grads = grads.tensors().into_iter().cloned().collect();
+    check_params(&mut glu, grads, GLUProjection::parameters_mut, |m| weighted_sum(&m.forward(&x), &r));
+}
+
+#[test]
+fn patch_embed_gradients() {
+    let mut pe = PatchEmbed::new_with_rng(4, 6, &mut StdRng::seed_from_u64(4));
+    pe.weight = pattern((6, 48), 1.0) * 0.1;
+    let img: Array3<f32> = pattern((3, 8, 8), 0.0);
+    let r = pattern((4, 6), 3.0);
+
+    let (_, cache) = pe.forward_train(&img);
+    let (d_img, grads) = pe.backward(&cache, &r);
+
+    check_input(&img, &d_img, |img| weighted_sum(&pe.forward(img), &r));
+    let grads = grads.tensors().into_iter().cloned().collect();
+    check_params(&mut pe, grads, PatchEmbed::parameters_mut, |m| weighted_sum(&m.forward(&img), &r));
+}
+
+// 用 GLU 拟合固定目标, 两种优化器都应使 MSE 下降
+#[test]
+fn optimizers_reduce_loss() {
+    let x = pattern((8, 6), 0.0);
+    let target = pattern((8, 4), 1.0).mapv(f32::abs);
+    let mse = |glu: &GLUProjection| (&glu.forward(&x) - &target).mapv(|v| v * v).mean().unwrap();
+
+    let mut sgd_glu = GLUProjection::new_with_rng(6, 4, &mut StdRng::seed_from_u64(5));
+    let mut adam_glu = GLUProjection::new_with_rng(6, 4, &mut StdRng::seed_from_u64(5));
+    let initial = mse(&sgd_glu);
+
+    let mut sgd = Sgd::with_momentum(0.05, 0.9);
+    let mut adam = AdamW::new(0.01);
+    for _ in 0..200 {
+        for (glu, use_adam) in [(&mut sgd_glu, false), (&mut adam_glu, true)] {
+            let (out, cache) = glu.forward_train(&x);
+            let grad_out = (&out - &target) * (2.0 / out.len() as f32);
+            let (_, grads) = glu.backward(&cache, &grad_out);
+            if use_adam {
+                adam.step(glu.parameters_mut(), grads.tensors());
+            } else {
+                sgd.step(glu.parameters_mut(), grads.tensors());
+            }
+        }
+    }
+
+    assert!(mse(&sgd_glu) < initial * 0.5, "sgd: {} -> {}", initial, mse(&sgd_glu));
+    assert!(mse(&adam_glu) < initial * 0.5, "adamw: {} -> {}", initial, mse(&adam_glu));
+}
@@ -0,0 +1,65 @@
+// tests/init.rs
+
+use cogvlm_image_preprocessor::encoder::{EncoderConfig, VisionEncoder};
+use cogvlm_image_preprocessor::init::Initializer;
+use ndarray::{Array2, Array3};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+fn std_dev(a: &Array2<f32>) -> f32 {
+    a.std(0.0)
+}
+
+#[test]
+fn schemes_follow_their_scale() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let (fan_in, fan_out) = (256, 128);
+
+    let xavier = Initializer::Xavier.linear(fan_in, fan_out, &mut rng);
+    let limit = (6.0 / (fan_in + fan_out) as f32).sqrt();
+    assert!(xavier.iter().all(|v| v.abs() <= limit));
+    assert!((std_dev(&xavier) - limit / 3f32.sqrt()).abs() < 0.05 * limit);
+
+    let kaiming = Initializer::Kaiming.linear(fan_in, fan_out, &mut rng);
+    assert!((std_dev(&kaiming) - (2.0 / fan_in as f32).sqrt()).abs() < 0.005);
+
+    let trunc = Initializer::TruncatedNormal { std: 0.02 }.linear(fan_in, fan_out, &mut rng);
+    assert!(trunc.iter().all(|v| v.abs() <= 0.04));
+    // 截断到 ±2σ 后标准差约为 0.88σ
+    assert!((std_dev(&trunc) - 0.88 * 0.02).abs() < 0.001);
+
+    assert!(Initializer::Zeros.linear(3, 4, &mut rng).iter().all(|&v| v == 0.0));
+}
+
+fn small_config(seed: u64) -> EncoderConfig {
+    EncoderConfig {
+        image_size: 32,
+        patch_size: 8,
+        embed_dim: 32,
+        num_heads: 4,
+        ff_dim: 64,
+        num_layers: 4,
+        out_dim: 16,
+        seed: Some(seed),
+        ..EncoderConfig::default()
+    }
+}
+
+#[test]
+fn seeded_encoders_are_reproducible() {
+    let img = Array3::from_shape_fn((3, 32, 32), |(c, y, x)| ((c * 7 + y * 3 + x) as f32 * 0.1).sin());
+    let a = VisionEncoder::new(small_config(42)).forward(&img);
+    let b = VisionEncoder::new(small_config(42)).forward(&img);
+    let c = VisionEncoder::new(small_config(43)).forward(&img);
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+}
+
+// 随机初始化的编码器输出应保持在合理范围, 而不是随层数爆炸
+#[test]
+fn random_init_activations_stay_bounded() {
+    let img = Array3::from_shape_fn((3, 32, 32), |(c, y, x)| ((c * 7 + y * 3 + x) as f32 * 0.1).sin() * 2.0);
+    let out = VisionEncoder::new(small_config(7)).forward(&img);
+    assert!(out.iter().all(|v| v.is_finite()));
+    assert!(out.iter().fold(0.0f32, |m, v| m.max(v.abs())) < 50.0);
+}
@@ -0,0 +1,99 @@
+// tests/metadata.rs
+
+use cogvlm_image_preprocessor::augment::{Augmentation, Transform};
+use cogvlm_image_preprocessor::encoder::EncoderConfig;
+use cogvlm_image_preprocessor::processor::{
+    process_image_files_in_batch, process_images_in_batch, ImageProcessor, NativeResolution, ResizeStrategy,
+};
+use cogvlm_image_preprocessor::resampler::DownsampleKind;
+use image::{DynamicImage, RgbImage};
+use ndarray::Axis;
+
+fn image(w: u32, h: u32, seed: u8) -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(w, h, |x, y| image::Rgb([x as u8, y as u8, seed])))
+}
+
+#[test]
+fn batch_metadata_describes_stretch_resize() {
+    let config = EncoderConfig { image_size: 64, patch_size: 16, downsample: DownsampleKind::AvgPool, ..Default::default() };
+    let processor = ImageProcessor::for_encoder(&config);
+    let out = process_images_in_batch(vec![image(128, 32, 0), image(64, 64, 1)], &processor);
+
+    assert_eq!(out.metadata.len(), 2);
+    let meta = &out.metadata[0];
+    assert_eq!(meta.original_size, (128, 32));
+    assert_eq!(meta.processed_size, (64, 64));
+    assert_eq!(meta.scale, (0.5, 2.0));
+    assert_eq!(meta.crop, (0.0, 0.0, 128.0, 32.0));
+    assert!(!meta.flipped);
+    assert_eq!(meta.resize, ResizeStrategy::Stretch);
+    assert_eq!(meta.patch_grid, (4, 4));
+    assert_eq!(meta.num_tokens, 4);
+    assert!(!meta.augmented);
+    assert_eq!(meta.source, None);
+    assert_eq!(meta.hash, None);
+
+    // 输出右下角映射回原图右下角
+    assert_eq!(meta.to_original(64.0, 64.0), (128.0, 32.0));
+}
+
+#[test]
+fn native_metadata_matches_preprocessed_shape() {
+    let processor = ImageProcessor::new(224);
+    let native = NativeResolution::default();
+    let img = image(300, 200, 0);
+    let meta = processor.native_metadata(&img, &native);
+    let x = processor.preprocess_native(&img, &native);
+
+    assert_eq!(meta.resize, ResizeStrategy::Native);
+    assert_eq!((meta.processed_size.1 as usize, meta.processed_size.0 as usize), (x.shape()[1], x.shape()[2]));
+    assert_eq!(meta.patch_grid, (x.shape()[1] / 16, x.shape()[2] / 16));
+    assert_eq!(meta.num_tokens, meta.patch_grid.0 * meta.patch_grid.1);
+}
+
+#[test]
+fn hash_depends_on_pixels_and_files_record_source() {
+    let processor = ImageProcessor { hash_pixels: true, ..ImageProcessor::new(32) };
+    assert!(processor.metadata(&image(20, 10, 3)).hash.is_some());
+    assert_eq!(processor.metadata(&image(20, 10, 3)).hash, processor.metadata(&image(20, 10, 3)).hash);
+    assert_ne!(processor.metadata(&image(20, 10, 3)).hash, processor.metadata(&image(20, 10, 4)).hash);
+    assert_ne!(processor.metadata(&image(20, 10, 3)).hash, processor.metadata(&image(10, 20, 3)).hash);
+
+    let dir = std::env::temp_dir().join(format!("cogvlm-metadata-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("a.png");
+    image(20, 10, 3).save(&path).unwrap();
+
+    let out = process_image_files_in_batch(&[&path], &processor).unwrap();
+    assert_eq!(out.metadata[0].source.as_deref(), Some(path.as_path()));
+    assert_eq!(out.metadata[0].hash, processor.metadata(&image(20, 10, 3)).hash);
+    assert!(process_image_files_in_batch(&[dir.join("missing.png")], &processor).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn augmented_crop_and_flip_map_back_to_the_original() {
+    // R = 2x, G = 4y 的线性渐变: 输出像素的值直接给出它在原图中的位置
+    let original = DynamicImage::ImageRgb8(RgbImage::from_fn(120, 60, |x, y| image::Rgb([2 * x as u8, 4 * y as u8, 0])));
+    let augment = Augmentation::new(5)
+        .then(Transform::HorizontalFlip { p: 1.0 })
+        .then(Transform::RandomResizedCrop { scale: (0.25, 0.25), ratio: (1.0, 1.0) });
+    let processor = ImageProcessor::new(64).with_augmentation(augment);
+    let out = process_images_in_batch(vec![original], &processor);
+    let meta = &out.metadata[0];
+
+    // 64x64 中取 32x32 的框, 换到原图为 60x30
+    assert!(meta.augmented && meta.flipped);
+    assert!((meta.crop.2 - 60.0).abs() < 1e-3 && (meta.crop.3 - 30.0).abs() < 1e-3);
+    assert!((meta.scale.0 - 64.0 / 60.0).abs() < 1e-4 && (meta.scale.1 - 64.0 / 30.0).abs() < 1e-4);
+
+    let tensor = out.image.index_axis(Axis(0), 0);
+    for &(x, y) in &[(10usize, 12usize), (40, 50), (31, 5)] {
+        let (ox, oy) = meta.to_original(x as f32 + 0.5, y as f32 + 0.5);
+        // 反归一化得到 0..255 的像素值, 原图像素 i 的中心在 i + 0.5
+        let r = (tensor[[0, y, x]] * processor.std[0] + processor.mean[0]) * 255.0;
+        let g = (tensor[[1, y, x]] * processor.std[1] + processor.mean[1]) * 255.0;
+        assert!((r / 2.0 - (ox - 0.5)).abs() < 1.0, "x: pixel says {}, metadata says {}", r / 2.0, ox - 0.5);
+        assert!((g / 4.0 - (oy - 0.5)).abs() < 1.0, "y: pixel says {}, metadata says {}", g / 4.0, oy - 0.5);
+    }
+}
@@ -0,0 +1,96 @@
+// tests/native_resolution.rs
+
+use cogvlm_image_preprocessor::encoder::{EncoderConfig, VisionEncoder};
+use cogvlm_image_preprocessor::processor::{ImageProcessor, NativeResolution};
+use cogvlm_image_preprocessor::resampler::DownsampleKind;
+use image::{DynamicImage, RgbImage};
+use ndarray::Array3;
+
+fn config(downsample: DownsampleKind) -> EncoderConfig {
+    EncoderConfig {
+        image_size: 32,
+        patch_size: 8,
+        embed_dim: 16,
+        num_heads: 2,
+        ff_dim: 32,
+        num_layers: 2,
+        out_dim: 8,
+        downsample,
+        seed: Some(3),
+        rope_2d: true,
+        ..EncoderConfig::default()
+    }
+}
+
+fn image(h: usize, w: usize) -> Array3<f32> {
+    Array3::from_shape_fn((3, h, w), |(c, y, x)| ((c * 5 + y * 3 + x) as f32 * 0.07).cos())
+}
+
+#[test]
+fn target_size_keeps_aspect_ratio_within_budget() {
+    let native = NativeResolution { factor: 16, min_pixels: 64 * 64, max_pixels: 256 * 256 };
+    for &(w, h) in &[(1000, 500), (300, 900), (40, 30), (250, 250), (2000, 400)] {
+        let (tw, th) = native.target_size(w, h);
+        assert!(tw % 16 == 0 && th % 16 == 0, "{}x{} -> {}x{}", w, h, tw, th);
+        assert!(tw * th <= native.max_pixels && tw * th >= native.min_pixels, "{}x{} -> {}x{}", w, h, tw, th);
+        let (a, b) = (w as f32 / h as f32, tw as f32 / th as f32);
+        assert!((a / b).ln().abs() < 0.25, "{}x{} -> {}x{}", w, h, tw, th);
+    }
+    // 已满足约束的尺寸不变
+    assert_eq!(native.target_size(160, 96), (160, 96));
+
+    // 极端宽高比: 短边钳到 factor 后仍不超出预算
+    let native = NativeResolution::default();
+    for &(w, h) in &[(100000, 10), (10, 100000)] {
+        let (tw, th) = native.target_size(w, h);
+        assert!(tw % 16 == 0 && th % 16 == 0 && tw.min(th) == 16, "{}x{} -> {}x{}", w, h, tw, th);
+        assert!(tw * th <= native.max_pixels, "{}x{} -> {}x{}", w, h, tw, th);
+    }
+}
+
+#[test]
+fn preprocess_native_outputs_patch_multiples() {
+    let processor = ImageProcessor { patch_size: 8, ..ImageProcessor::new(224) };
+    let native = NativeResolution { factor: 8, min_pixels: 0, max_pixels: 64 * 64 };
+    let img = DynamicImage::ImageRgb8(RgbImage::from_fn(90, 45, |x, y| image::Rgb([x as u8, y as u8, 7])));
+    assert_eq!(processor.preprocess_native(&img, &native).dim(), (3, 40, 88));
+}
+
+#[test]
+#[should_panic(expected = "must be a positive multiple of patch_size")]
+fn preprocess_native_rejects_factor_not_multiple_of_patch_size() {
+    let processor = ImageProcessor::for_encoder(&config(DownsampleKind::None));
+    let img = DynamicImage::ImageRgb8(RgbImage::new(40, 30));
+    processor.preprocess_native(&img, &NativeResolution { factor: 12, ..NativeResolution::default() });
+}
+
+#[test]
+fn factor_follows_the_encoder() {
+    assert_eq!(NativeResolution::for_encoder(&config(DownsampleKind::None)).factor, 8);
+    assert_eq!(NativeResolution::for_encoder(&config(DownsampleKind::AvgPool)).factor, 16);
+
+    let encoder = VisionEncoder::new(config(DownsampleKind::Conv));
+    let processor = ImageProcessor::for_encoder(&encoder.config);
+    let native = NativeResolution { max_pixels: 48 * 48, ..NativeResolution::for_encoder(&encoder.config) };
+    let img = DynamicImage::ImageRgb8(RgbImage::from_fn(90, 45, |x, y| image::Rgb([x as u8, y as u8, 7])));
+    let meta = processor.native_metadata(&img, &native);
+    assert_eq!(encoder.forward_native(&processor.preprocess_native(&img, &native)).nrows(), meta.num_tokens);
+}
+
+#[test]
+fn forward_native_matches_forward_on_configured_grid() {
+    let encoder = VisionEncoder::new(config(DownsampleKind::None));
+    let img = image(32, 32);
+    assert_eq!(encoder.forward_native(&img), encoder.forward(&img));
+}
+
+#[test]
+fn forward_native_handles_non_square_grids() {
+    for (kind, tokens) in [(DownsampleKind::None, 3 * 6), (DownsampleKind::AvgPool, 2 * 3), (DownsampleKind::Conv, 2 * 3)] {
+        let encoder = VisionEncoder::new(config(kind));
+        let outputs = encoder.forward_native_batch(&[image(24, 48), image(48, 24)]);
+        assert_eq!(outputs[0].dim(), (tokens, 8));
+        assert_eq!(outputs[1].dim(), (tokens, 8));
+        assert_ne!(outputs[0], outputs[1]);
+    }
+}
@@ -0,0 +1,80 @@
+// tests/packing.rs
+
+use cogvlm_image_preprocessor::encoder::{EncoderConfig, VisionEncoder};
+use cogvlm_image_preprocessor::packing::{block_diagonal_mask, cu_seqlens, pack, unpack};
+use cogvlm_image_preprocessor::resampler::DownsampleKind;
+use cogvlm_image_preprocessor::rope::{RopeCache, RopeConfig};
+use cogvlm_image_preprocessor::transformer::MultiHeadAttention;
+use ndarray::{Array2, Array3};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+fn input(seq_len: usize, dim: usize, phase: f32) -> Array2<f32> {
+    Array2::from_shape_fn((seq_len, dim), |(i, j)| ((i * dim + j) as f32 * 0.37 + phase).sin())
+}
+
+fn max_abs_diff(a: &Array2<f32>, b: &Array2<f32>) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max)
+}
+
+#[test]
+fn pack_and_unpack_round_trip() {
+    let seqs = vec![input(3, 4, 0.0), input(5, 4, 1.0), input(1, 4, 2.0)];
+    let (packed, offsets) = pack(&seqs);
+    assert_eq!(packed.dim(), (9, 4));
+    assert_eq!(offsets, vec![0, 3, 8, 9]);
+    assert_eq!(offsets, cu_seqlens(&[3, 5, 1]));
+    assert_eq!(unpack(&packed, &offsets), seqs);
+
+    let mask = block_diagonal_mask(&offsets);
+    assert!(mask[[3, 7]] && !mask[[2, 3]] && mask[[8, 8]] && !mask[[8, 0]]);
+}
+
+#[test]
+fn varlen_attention_matches_separate_and_masked_calls() {
+    let mha = MultiHeadAttention::new_with_rng(16, 4, 2, &mut StdRng::seed_from_u64(1));
+    let config = RopeConfig::new(4);
+    let (rope_a, rope_b) = (RopeCache::from_config(6, config.clone()), RopeCache::from_config(9, config));
+    let seqs = vec![input(6, 16, 0.0), input(9, 16, 1.0)];
+    let (packed, offsets) = pack(&seqs);
+
+    let rope = RopeCache::packed(&[(&rope_a, 6), (&rope_b, 9)]);
+    let out = unpack(&mha.forward_varlen(&packed, &offsets, Some(&rope)), &offsets);
+    assert!(max_abs_diff(&out[0], &mha.forward_with_rope(&seqs[0], Some(&rope_a))) < 1e-5);
+    assert!(max_abs_diff(&out[1], &mha.forward_with_rope(&seqs[1], Some(&rope_b))) < 1e-5);
+
+    // 不带 rope 时与块对角掩码的完整注意力相同
+    let masked = mha.forward_cross(&packed, &packed, Some(&block_diagonal_mask(&offsets)));
+    assert!(max_abs_diff(&mha.forward_varlen(&packed, &offsets, None), &masked) < 1e-5);
+}
+
+#[test]
+fn forward_packed_matches_per_image_encoding() {
+    let images: Vec<_> = [(24, 48), (32, 32), (16, 8)]
+        .iter()
+        .map(|&(h, w)| Array3::from_shape_fn((3, h, w), |(c, y, x)| ((c * 5 + y * 3 + x) as f32 * 0.07).cos()))
+        .collect();
+    for (rope_2d, downsample) in [(true, DownsampleKind::None), (false, DownsampleKind::Resampler)] {
+        let encoder = VisionEncoder::new(EncoderConfig {
+            image_size: 32,
+            patch_size: 8,
+            embed_dim: 16,
+            num_heads: 2,
+            num_kv_heads: Some(1),
+            ff_dim: 32,
+            num_layers: 2,
+            out_dim: 8,
+            downsample,
+            num_queries: 4,
+            seed: Some(7),
+            rope_2d,
+        });
+        let packed = encoder.forward_packed(&images);
+        let separate = encoder.forward_native_batch(&images);
+        assert_eq!(packed.len(), 3);
+        for (a, b) in packed.iter().zip(&separate) {
+            assert_eq!(a.dim(), b.dim());
+            assert!(max_abs_diff(a, b) < 1e-4);
+        }
+    }
+}
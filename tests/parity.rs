@@ -0,0 +1,121 @@
+// tests/parity.rs
+//
+// 与参考实现逐阶段对齐: 每个阶段读入 fixtures 中的输入和期望输出,
+// 检查 max-abs 误差和余弦相似度。fixtures 由 scripts/gen_parity_fixtures.py 生成。
+
+use cogvlm_image_preprocessor::glu_projection::GLUProjection;
+use cogvlm_image_preprocessor::patch_embed::PatchEmbed;
+use cogvlm_image_preprocessor::processor::ImageProcessor;
+use cogvlm_image_preprocessor::transformer::TransformerLayer;
+use image::{DynamicImage, RgbImage};
+use ndarray::{Array, Array2, Array3, Dimension};
+use ndarray_npy::{read_npy, ReadNpyExt};
+use std::path::PathBuf;
+
+// 与 scripts/gen_parity_fixtures.py 保持一致
+const IMAGE_SIZE: u32 = 56;
+const PATCH: usize = 14;
+const EMBED_DIM: usize = 32;
+const NUM_HEADS: usize = 4;
+const FF_DIM: usize = 64;
+const NUM_LAYERS: usize = 2;
+const GLU_OUT: usize = 16;
+
+const MAX_ABS: f32 = 1e-4;
+const MIN_COS: f32 = 0.99999;
+// PIL 以定点系数分两趟缩放, 每趟取整到 u8, 且在边界处对权重重新归一化;
+// 允许相差几个 u8 灰度 (按最小的 std 换算到归一化后的值)
+const PROCESSOR_MAX_ABS: f32 = 4.0 / 255.0 / 0.2613;
+const PROCESSOR_MIN_COS: f32 = 0.9999;
+
+fn load<T: ReadNpyExt>(name: &str) -> T {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/parity")
+        .join(format!("{}.npy", name));
+    read_npy(&path).unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e))
+}
+
+fn assert_close<D: Dimension>(stage: &str, actual: &Array<f32, D>, expected: &Array<f32, D>, max_abs: f32, min_cos: f32) {
+    assert_eq!(actual.shape(), expected.shape(), "{}: shape mismatch", stage);
+
+    let mut worst = 0.0f32;
+    let (mut dot, mut na, mut ne) = (0.0f64, 0.0f64, 0.0f64);
+    for (&a, &e) in actual.iter().zip(expected.iter()) {
+        worst = worst.max((a - e).abs());
+        dot += a as f64 * e as f64;
+        na += a as f64 * a as f64;
+        ne += e as f64 * e as f64;
+    }
+    let cos = (dot / (na.sqrt() * ne.sqrt()).max(f64::MIN_POSITIVE)) as f32;
+
+    assert!(worst <= max_abs, "{}: max abs diff {} > {}", stage, worst, max_abs);
+    assert!(cos >= min_cos, "{}: cosine similarity {} < {}", stage, cos, min_cos);
+}
+
+fn load_layer(idx: usize) -> TransformerLayer {
+    let p = |name: &str| -> Array2<f32> { load(&format!("layer{}_{}", idx, name)) };
+    let mut layer = TransformerLayer::new(EMBED_DIM, FF_DIM, NUM_HEADS);
+    layer.ln1.gamma = p("ln1_gamma");
+    layer.ln1.beta = p("ln1_beta");
+    layer.ln2.gamma = p("ln2_gamma");
+    layer.ln2.beta = p("ln2_beta");
+    layer.mha.wq = p("wq");
+    layer.mha.wk = p("wk");
+    layer.mha.wv = p("wv");
+    layer.mha.wo = p("wo");
+    layer.ffn.w1 = p("w1");
+    layer.ffn.b1 = p("b1");
+    layer.ffn.w2 = p("w2");
+    layer.ffn.b2 = p("b2");
+    layer
+}
+
+#[test]
+fn processor_matches_reference() {
+    // 原图为非正方形的 IMAGE_W x IMAGE_H, 经 bicubic 缩放到 IMAGE_SIZE
+    let raw: Array3<u8> = load("image_u8");
+    let (h, w, _) = raw.dim();
+    let rgb = RgbImage::from_raw(w as u32, h as u32, raw.into_raw_vec()).unwrap();
+
+    let processor = ImageProcessor::new(IMAGE_SIZE);
+    let actual = processor.preprocess(&DynamicImage::ImageRgb8(rgb));
+    let expected: Array3<f32> = load("processor_out");
+    assert_close("processor", &actual, &expected, PROCESSOR_MAX_ABS, PROCESSOR_MIN_COS);
+}
+
+#[test]
+fn patch_embed_matches_reference() {
+    let input: Array3<f32> = load("processor_out");
+    let mut embed = PatchEmbed::new(PATCH, EMBED_DIM);
+    embed.weight = load("patch_embed_weight");
+    embed.bias = Some(load("patch_embed_bias"));
+
+    let actual = embed.forward(&input);
+    let expected: Array2<f32> = load("patch_embed_out");
+    assert_close("patch_embed", &actual, &expected, MAX_ABS, MIN_COS);
+}
+
+#[test]
+fn transformer_layers_match_reference() {
+    let mut input: Array2<f32> = load("patch_embed_out");
+    for idx in 0..NUM_LAYERS {
+        let actual = load_layer(idx).forward(&input);
+        let expected: Array2<f32> = load(&format!("layer{}_out", idx));
+        assert_close(&format!("layer{}", idx), &actual, &expected, MAX_ABS, MIN_COS);
+        // 下一层使用参考输出, 避免误差逐层累积
+        input = expected;
+    }
+}
+
+#[test]
+fn glu_projection_matches_reference() {
+    let input: Array2<f32> = load(&format!("layer{}_out", NUM_LAYERS - 1));
+    let mut glu = GLUProjection::new(EMBED_DIM, GLU_OUT);
+    glu.weight = load("glu_weight");
+    glu.bias = Some(load("glu_bias"));
+
+    let expected: Array2<f32> = load("glu_out");
+    assert_close("glu", &glu.forward(&input), &expected, MAX_ABS, MIN_COS);
+    assert_close("glu_rayon", &glu.forward_rayon(&input), &expected, MAX_ABS, MIN_COS);
+    assert_close("glu_rayon_simd", &glu.forward_rayon_simd(&input), &expected, MAX_ABS, MIN_COS);
+}
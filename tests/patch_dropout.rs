@@ -0,0 +1,78 @@
+// tests/patch_dropout.rs
+
+use cogvlm_image_preprocessor::patch_dropout::PatchDropout;
+use ndarray::Array2;
+
+// 第 i 行的值全为 i, 便于从输出反推原始下标
+fn indexed_tokens(n: usize, dim: usize) -> Array2<f32> {
+    Array2::from_shape_fn((n, dim), |(i, _)| i as f32)
+}
+
+fn assert_rows_match(tokens: &Array2<f32>, indices: &[usize]) {
+    assert_eq!(tokens.shape()[0], indices.len());
+    for (row, &i) in tokens.outer_iter().zip(indices) {
+        assert!(row.iter().all(|&v| v == i as f32));
+    }
+}
+
+#[test]
+#[should_panic(expected = "block_size must be positive")]
+fn block_dropout_rejects_zero_block_size() {
+    PatchDropout::new(0.5, false).forward_block(&indexed_tokens(16, 2), (4, 4), 0);
+}
+
+#[test]
+fn inputs_without_patch_tokens_are_returned_unchanged() {
+    let dropout = PatchDropout::new(0.5, true);
+    for n in [0, 1] {
+        let x = indexed_tokens(n, 3);
+        let expected: Vec<usize> = (0..n).collect();
+        assert_eq!(dropout.forward_block(&x, (0, 0), 2), (x.clone(), expected.clone()));
+        assert_eq!(dropout.forward_saliency(&x, &[]), (x.clone(), expected.clone()));
+        assert_eq!(dropout.forward_merge(&x).1.len(), n);
+    }
+}
+
+#[test]
+fn block_dropout_keeps_whole_blocks() {
+    // cls + 4x4 网格, 2x2 块, 保留一半
+    let dropout = PatchDropout::new(0.5, true);
+    let x = indexed_tokens(17, 3);
+    let (tokens, indices) = dropout.forward_block(&x, (4, 4), 2);
+
+    assert_eq!(indices[0], 0);
+    assert_eq!(indices.len(), 1 + 8);
+    assert_rows_match(&tokens, &indices);
+    for &i in &indices[1..] {
+        let (r, c) = ((i - 1) / 4, (i - 1) % 4);
+        let block: Vec<usize> = (0..4).map(|k| 1 + (r / 2 * 2 + k / 2) * 4 + c / 2 * 2 + k % 2).collect();
+        assert!(block.iter().all(|b| indices.contains(b)), "block of {} partially kept", i);
+    }
+}
+
+#[test]
+fn saliency_dropout_keeps_top_scores_in_order() {
+    let dropout = PatchDropout::new(0.5, false);
+    let x = indexed_tokens(6, 2);
+    let scores = [0.1, 0.9, 0.3, 0.8, 0.7, 0.2];
+    let (tokens, indices) = dropout.forward_saliency(&x, &scores);
+    assert_eq!(indices, vec![1, 3, 4]);
+    assert_rows_match(&tokens, &indices);
+}
+
+#[test]
+fn merge_combines_similar_tokens() {
+    // 两组方向相反的 token, 合并到 2 个时每组各成一个 token
+    let dropout = PatchDropout::new(0.6, true);
+    let mut x = Array2::<f32>::zeros((5, 2));
+    x.row_mut(0).fill(9.0);
+    for (i, v) in [(1, [1.0, 0.1]), (2, [2.0, 0.0]), (3, [-1.0, 0.0]), (4, [-2.0, -0.1])] {
+        x.row_mut(i).assign(&ndarray::arr1(&v));
+    }
+    let (tokens, sources) = dropout.forward_merge(&x);
+
+    assert_eq!(sources, vec![vec![0], vec![1, 2], vec![3, 4]]);
+    assert_eq!(tokens.row(0).to_vec(), vec![9.0, 9.0]);
+    assert!((tokens[[1, 0]] - 1.5).abs() < 1e-6);
+    assert!((tokens[[2, 0]] + 1.5).abs() < 1e-6);
+}
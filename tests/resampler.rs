@@ -0,0 +1,73 @@
+// tests/resampler.rs
+
+use cogvlm_image_preprocessor::encoder::{EncoderConfig, VisionEncoder};
+use cogvlm_image_preprocessor::resampler::{avg_pool_2x2, ConvDownsampler, DownsampleKind, TokenResampler};
+use ndarray::{Array2, Array3, array};
+
+fn grid_tokens(gh: usize, gw: usize, dim: usize) -> Array2<f32> {
+    Array2::from_shape_fn((gh * gw, dim), |(t, k)| (t * dim + k) as f32)
+}
+
+#[test]
+fn avg_pool_averages_windows_including_odd_edges() {
+    // 3x3 网格, 1 维特征, token 值即其下标
+    let x = grid_tokens(3, 3, 1);
+    let pooled = avg_pool_2x2(&x, (3, 3));
+    let expected = array![[(0.0 + 1.0 + 3.0 + 4.0) / 4.0], [(2.0 + 5.0) / 2.0], [(6.0 + 7.0) / 2.0], [8.0]];
+    assert_eq!(pooled, expected);
+}
+
+#[test]
+fn conv_downsampler_matches_windowed_matmul() {
+    let dim = 3;
+    let conv = ConvDownsampler::new(dim);
+    let x = grid_tokens(2, 4, dim);
+    let out = conv.forward(&x, (2, 4));
+    assert_eq!(out.dim(), (2, dim));
+
+    // 第二个窗口为 token 2, 3, 6, 7
+    let window: Vec<f32> = [2, 3, 6, 7].iter().flat_map(|&t| x.row(t).to_vec()).collect();
+    let expected = Array2::from_shape_vec((1, 4 * dim), window).unwrap().dot(&conv.weight) + &conv.bias;
+    for (a, b) in out.row(1).iter().zip(expected.row(0).iter()) {
+        assert!((a - b).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn resampler_output_length_is_fixed() {
+    let resampler = TokenResampler::new(16, 5, 4, 32);
+    for seq_len in [3, 49, 100] {
+        assert_eq!(resampler.forward(&grid_tokens(seq_len, 1, 16)).dim(), (5, 16));
+    }
+}
+
+#[test]
+fn encoder_token_count_follows_downsample_kind() {
+    let base = EncoderConfig {
+        image_size: 40,
+        patch_size: 8,
+        embed_dim: 16,
+        num_heads: 2,
+        ff_dim: 32,
+        num_layers: 1,
+        out_dim: 8,
+        num_queries: 7,
+        ..EncoderConfig::default()
+    };
+    let img = Array3::<f32>::from_elem((3, 40, 40), 0.5);
+
+    // 5x5 网格
+    for (kind, tokens) in [
+        (DownsampleKind::None, 25),
+        (DownsampleKind::AvgPool, 9),
+        (DownsampleKind::Conv, 9),
+        (DownsampleKind::Resampler, 7),
+    ] {
+        let encoder = VisionEncoder::new(EncoderConfig { downsample: kind, ..base.clone() });
+        assert_eq!(encoder.downsampler.num_tokens(encoder.config.grid()), tokens);
+        assert_eq!(encoder.forward(&img).dim(), (tokens, 8));
+    }
+
+    let parsed: EncoderConfig = serde_json::from_str(r#"{"downsample": "avg_pool"}"#).unwrap();
+    assert_eq!(parsed.downsample, DownsampleKind::AvgPool);
+}
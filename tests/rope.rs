@@ -0,0 +1,197 @@
+// tests/rope.rs
+// apply_rope 系列已弃用, 这里仍作为参考实现交叉验证
+#![allow(deprecated)]
+
+use cogvlm_image_preprocessor::rope::{
+    apply_rope, apply_rope_parallel, apply_rope_simd_parallel, apply_rope_with_config, RopeCache, RopeConfig,
+    RopePairing, RopeScaling,
+};
+use ndarray::Array2;
+
+fn input(seq_len: usize, dim: usize) -> Array2<f32> {
+    Array2::from_shape_fn((seq_len, dim), |(i, j)| ((i * dim + j) as f32 * 0.37).sin())
+}
+
+fn max_abs_diff(a: &Array2<f32>, b: &Array2<f32>) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max)
+}
+
+#[test]
+fn cached_kernels_match_reference() {
+    // 36 不是 16 的倍数, 覆盖 SIMD 尾部
+    for &(seq_len, dim) in &[(1, 2), (17, 36), (64, 128)] {
+        let mut expected = input(seq_len, dim);
+        apply_rope(&mut expected, dim);
+
+        let cache = RopeCache::new(seq_len + 5, dim, 10000.0);
+        let mut scalar = input(seq_len, dim);
+        cache.apply(&mut scalar);
+        let mut simd = input(seq_len, dim);
+        cache.apply_simd(&mut simd);
+        let mut parallel = input(seq_len, dim);
+        apply_rope_parallel(&mut parallel, dim);
+        let mut simd_parallel = input(seq_len, dim);
+        apply_rope_simd_parallel(&mut simd_parallel, dim);
+
+        for out in [&scalar, &simd, &parallel, &simd_parallel] {
+            assert!(max_abs_diff(out, &expected) < 1e-6, "seq_len={} dim={}", seq_len, dim);
+        }
+    }
+}
+
+#[test]
+fn cache_only_rotates_first_dim_columns() {
+    let mut x = input(8, 20);
+    let original = x.clone();
+    RopeCache::new(8, 16, 10000.0).apply_simd(&mut x);
+    assert_eq!(x.slice(ndarray::s![.., 16..]), original.slice(ndarray::s![.., 16..]));
+    // 位置 0 不旋转
+    assert_eq!(x.row(0), original.row(0));
+}
+
+#[test]
+fn all_config_variants_match_reference() {
+    let pairings = [RopePairing::Interleaved, RopePairing::HalfSplit];
+    let scalings = [
+        RopeScaling::None,
+        RopeScaling::Linear { factor: 2.0 },
+        RopeScaling::Ntk { factor: 4.0 },
+    ];
+    for &pairing in &pairings {
+        for &scaling in &scalings {
+            for &rotary_dim in &[48, 32, 20] {
+                let config = RopeConfig { rotary_dim, base: 500.0, pairing, scaling, ..RopeConfig::new(48) };
+                let mut expected = input(33, 48);
+                apply_rope_with_config(&mut expected, &config);
+
+                let cache = RopeCache::from_config(33, config.clone());
+                let mut scalar = input(33, 48);
+                cache.apply(&mut scalar);
+                let mut simd = input(33, 48);
+                cache.apply_simd(&mut simd);
+
+                let msg = format!("{:?}", config);
+                assert!(max_abs_diff(&scalar, &expected) < 1e-6, "{}", msg);
+                assert!(max_abs_diff(&simd, &expected) < 1e-6, "{}", msg);
+                // 部分旋转时其余列不变
+                assert_eq!(expected.slice(ndarray::s![.., rotary_dim..]), input(33, 48).slice(ndarray::s![.., rotary_dim..]), "{}", msg);
+            }
+        }
+    }
+}
+
+#[test]
+fn default_config_matches_original_kernel() {
+    let mut expected = input(10, 16);
+    apply_rope(&mut expected, 16);
+    let mut actual = input(10, 16);
+    apply_rope_with_config(&mut actual, &RopeConfig::new(16));
+    assert!(max_abs_diff(&actual, &expected) < 1e-6);
+}
+
+#[test]
+fn half_split_is_interleaved_under_permutation() {
+    // rotate-half 的 (i, i+d/2) 重排成 (2i, 2i+1) 后结果一致
+    let dim = 16;
+    let x = input(7, dim);
+    let perm: Vec<usize> = (0..dim).map(|j| if j % 2 == 0 { j / 2 } else { j / 2 + dim / 2 }).collect();
+    let mut interleaved = Array2::from_shape_fn((7, dim), |(i, j)| x[[i, perm[j]]]);
+    apply_rope_with_config(&mut interleaved, &RopeConfig::new(dim));
+
+    let mut half = x.clone();
+    apply_rope_with_config(&mut half, &RopeConfig { pairing: RopePairing::HalfSplit, ..RopeConfig::new(dim) });
+    let half_permuted = Array2::from_shape_fn((7, dim), |(i, j)| half[[i, perm[j]]]);
+    assert!(max_abs_diff(&interleaved, &half_permuted) < 1e-6);
+}
+
+#[test]
+fn linear_scaling_interpolates_positions() {
+    // factor=2 时位置 2k 的结果等于未缩放时位置 k
+    let config = RopeConfig { scaling: RopeScaling::Linear { factor: 2.0 }, ..RopeConfig::new(8) };
+    let cache = RopeCache::from_config(8, config);
+    let plain = RopeCache::new(8, 8, 10000.0);
+    for k in 0..4 {
+        assert_eq!(cache.cos.row(2 * k), plain.cos.row(k));
+        assert_eq!(cache.sin.row(2 * k), plain.sin.row(k));
+    }
+}
+
+#[test]
+fn apply_heads_rotates_each_head_independently() {
+    let (heads, head_dim) = (3, 12);
+    let config = RopeConfig { pairing: RopePairing::HalfSplit, ..RopeConfig::new(head_dim) };
+    let cache = RopeCache::from_config(9, config.clone());
+
+    let mut actual = input(9, heads * head_dim);
+    cache.apply_heads_simd(&mut actual, heads);
+
+    for h in 0..heads {
+        let cols = ndarray::s![.., h * head_dim..(h + 1) * head_dim];
+        let mut expected = input(9, heads * head_dim).slice(cols).to_owned();
+        apply_rope_with_config(&mut expected, &config);
+        assert!(max_abs_diff(&actual.slice(cols).to_owned(), &expected) < 1e-6, "head {}", h);
+    }
+}
+
+#[test]
+fn rotated_scores_depend_only_on_relative_position() {
+    // 所有位置的 q/k 相同, 旋转后 q_i·k_j 只取决于 i - j
+    let dim = 16;
+    let q_row = input(1, dim);
+    let k_row = input(2, dim).slice(ndarray::s![1..2, ..]).to_owned();
+    let mut q = Array2::from_shape_fn((12, dim), |(_, j)| q_row[[0, j]]);
+    let mut k = Array2::from_shape_fn((12, dim), |(_, j)| k_row[[0, j]]);
+    let cache = RopeCache::from_config(12, RopeConfig::new(dim));
+    cache.apply_heads(&mut q, 1);
+    cache.apply_heads(&mut k, 1);
+
+    let scores = q.dot(&k.t());
+    for i in 0..11 {
+        for j in 0..11 {
+            assert!((scores[[i, j]] - scores[[i + 1, j + 1]]).abs() < 1e-4);
+        }
+    }
+}
+
+#[test]
+fn grid_rope_scores_depend_only_on_row_and_column_offsets() {
+    let (rows, cols, dim) = (4, 5, 16);
+    let q_row = input(1, dim);
+    let k_row = input(2, dim).slice(ndarray::s![1..2, ..]).to_owned();
+    let mut q = Array2::from_shape_fn((rows * cols, dim), |(_, j)| q_row[[0, j]]);
+    let mut k = Array2::from_shape_fn((rows * cols, dim), |(_, j)| k_row[[0, j]]);
+    let cache = RopeCache::from_grid((rows, cols), RopeConfig::new(dim));
+    cache.apply_heads(&mut q, 1);
+    cache.apply_heads(&mut k, 1);
+
+    // 两个位置同时平移 (dr, dc) 后得分不变
+    let scores = q.dot(&k.t());
+    let at = |r: usize, c: usize| r * cols + c;
+    for (dr, dc) in [(1, 0), (0, 1), (1, 1)] {
+        for i in 0..rows * cols {
+            for j in 0..rows * cols {
+                let (r1, c1, r2, c2) = (i / cols, i % cols, j / cols, j % cols);
+                if r1.max(r2) + dr >= rows || c1.max(c2) + dc >= cols {
+                    continue;
+                }
+                let shifted = scores[[at(r1 + dr, c1 + dc), at(r2 + dr, c2 + dc)]];
+                assert!((scores[[i, j]] - shifted).abs() < 1e-4, "({}, {}) -> ({}, {})", r1, c1, r2, c2);
+            }
+        }
+    }
+}
+
+#[test]
+fn grid_rope_single_row_matches_half_rotary_reference() {
+    // 1 x n 网格: 行号恒为 0, 只有后一半频率对按列号旋转
+    let (cols, dim) = (7, 16);
+    let config = RopeConfig::new(dim);
+    let mut actual = input(cols, dim);
+    RopeCache::from_grid((1, cols), config.clone()).apply(&mut actual);
+
+    let mut expected = input(cols, dim);
+    let mut second_half = expected.slice(ndarray::s![.., dim / 2..]).to_owned();
+    apply_rope_with_config(&mut second_half, &RopeConfig::new(dim / 2));
+    expected.slice_mut(ndarray::s![.., dim / 2..]).assign(&second_half);
+    assert!(max_abs_diff(&actual, &expected) < 1e-5);
+}
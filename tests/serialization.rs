@@ -0,0 +1,118 @@
+// tests/serialization.rs
+
+use cogvlm_image_preprocessor::encoder::{EncoderConfig, LoadError, VisionEncoder};
+use cogvlm_image_preprocessor::mmap::MappedWeights;
+use cogvlm_image_preprocessor::resampler::DownsampleKind;
+use ndarray::Array3;
+use std::path::PathBuf;
+
+fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("cogvlm-serialization-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&path);
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn config(downsample: DownsampleKind) -> EncoderConfig {
+    EncoderConfig {
+        image_size: 32,
+        patch_size: 8,
+        embed_dim: 16,
+        num_heads: 4,
+        num_kv_heads: Some(2),
+        ff_dim: 32,
+        num_layers: 2,
+        out_dim: 8,
+        downsample,
+        num_queries: 3,
+        ..EncoderConfig::default()
+    }
+}
+
+fn image() -> Array3<f32> {
+    Array3::from_shape_fn((3, 32, 32), |(c, y, x)| ((c * 5 + y * 3 + x) as f32 * 0.07).cos())
+}
+
+#[test]
+fn safetensors_round_trip_restores_config_and_weights() {
+    let path = temp_path("model.safetensors");
+    for kind in [DownsampleKind::Resampler, DownsampleKind::Conv] {
+        let encoder = VisionEncoder::new(config(kind));
+        encoder.save_safetensors(&path).unwrap();
+
+        let loaded = VisionEncoder::load(&path).unwrap();
+        assert_eq!(loaded.config, encoder.config);
+        assert_eq!(EncoderConfig::from_safetensors_header(&path).unwrap(), encoder.config);
+        assert_eq!(loaded.named_weights().len(), encoder.named_weights().len());
+        assert_eq!(loaded.forward(&image()), encoder.forward(&image()));
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn round_trip_without_optional_biases() {
+    let path = temp_path("no-bias.safetensors");
+    let dir = temp_path("no-bias-npy");
+    let mut encoder = VisionEncoder::new(config(DownsampleKind::None));
+    encoder.patch_embed.bias = None;
+    encoder.glu.bias = None;
+    encoder.save_safetensors(&path).unwrap();
+    encoder.save_npy_dir(&dir).unwrap();
+
+    for loaded in [VisionEncoder::load(&path).unwrap(), VisionEncoder::load_npy_dir(&dir).unwrap()] {
+        assert!(loaded.patch_embed.bias.is_none());
+        assert!(loaded.glu.bias.is_none());
+        assert_eq!(loaded.forward(&image()), encoder.forward(&image()));
+    }
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn npy_dir_round_trip() {
+    let dir = temp_path("npy");
+    let encoder = VisionEncoder::new(config(DownsampleKind::Resampler));
+    encoder.save_npy_dir(&dir).unwrap();
+    assert!(dir.join("config.json").is_file());
+    assert!(dir.join("layers.1.mha.wk.npy").is_file());
+
+    let loaded = VisionEncoder::load_npy_dir(&dir).unwrap();
+    assert_eq!(loaded.config, encoder.config);
+    assert_eq!(loaded.forward(&image()), encoder.forward(&image()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn load_requires_config_metadata() {
+    let path = temp_path("bare.safetensors");
+    let data = vec![0u8; 4];
+    let view = safetensors::tensor::TensorView::new(safetensors::Dtype::F32, vec![1], &data).unwrap();
+    safetensors::serialize_to_file(vec![("x", view)], &None, &path).unwrap();
+
+    assert!(matches!(VisionEncoder::load(&path), Err(LoadError::MissingConfig)));
+    assert!(matches!(EncoderConfig::from_safetensors_header(&path), Err(LoadError::MissingConfig)));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn mapped_weights_borrow_file_and_match_owned_load() {
+    let path = temp_path("mapped.safetensors");
+    for kind in [DownsampleKind::None, DownsampleKind::Resampler, DownsampleKind::Conv] {
+        let encoder = VisionEncoder::new(config(kind));
+        encoder.save_safetensors(&path).unwrap();
+
+        let weights = MappedWeights::open(&path).unwrap();
+        let mapped = weights.encoder().unwrap();
+        assert_eq!(mapped.config, encoder.config);
+        // 每个权重的数据都必须落在映射内, 而不是被复制出来
+        let range = weights.as_bytes().as_ptr_range();
+        for ((name, a), (_, b)) in mapped.named_weights().into_iter().zip(encoder.named_weights()) {
+            assert_eq!(a, b, "{}", name);
+            let bytes = a.len() * std::mem::size_of::<f32>();
+            let start = a.as_ptr() as *const u8;
+            assert!(range.contains(&start) && start.wrapping_add(bytes) <= range.end, "{} is not borrowed from the mmap", name);
+        }
+        assert_eq!(mapped.forward(&image()), VisionEncoder::load(&path).unwrap().forward(&image()));
+    }
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,122 @@
+// tests/server.rs
+#![cfg(feature = "server")]
+
+use base64::Engine;
+use cogvlm_image_preprocessor::encoder::{EncoderConfig, VisionEncoder};
+use cogvlm_image_preprocessor::server::{BatchQueue, EmbeddingServer, ServerConfig};
+use ndarray::Array3;
+use image::{DynamicImage, ImageOutputFormat, RgbImage};
+use std::io::{Cursor, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+
+fn small_config() -> EncoderConfig {
+    EncoderConfig {
+        image_size: 32,
+        patch_size: 8,
+        embed_dim: 16,
+        num_heads: 2,
+        ff_dim: 32,
+        num_layers: 1,
+        out_dim: 8,
+        ..EncoderConfig::default()
+    }
+}
+
+fn png_bytes() -> Vec<u8> {
+    let img = RgbImage::from_fn(40, 24, |x, y| image::Rgb([x as u8 * 5, y as u8 * 9, 128]));
+    let mut buf = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(img).write_to(&mut buf, ImageOutputFormat::Png).unwrap();
+    buf.into_inner()
+}
+
+// 发送一个 HTTP/1.0 请求并返回 (状态码, JSON)
+fn post(addr: SocketAddr, path: &str, content_type: &str, body: &[u8]) -> (u16, serde_json::Value) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "POST {} HTTP/1.0\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+        path,
+        content_type,
+        body.len()
+    )
+    .unwrap();
+    stream.write_all(body).unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let text = String::from_utf8(response).unwrap();
+    let status = text[9..12].parse().unwrap();
+    let json = serde_json::from_str(text.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+    (status, json)
+}
+
+#[test]
+fn serves_embeddings_and_batches_concurrent_requests() {
+    let config = ServerConfig {
+        addr: "127.0.0.1:0".to_string(),
+        max_batch_size: 4,
+        // 远大于测试耗时: 凑满 4 个才出批, 与请求到达的时机无关
+        batch_timeout: Duration::from_secs(60),
+        workers: 4,
+        max_body_bytes: 4096,
+    };
+    let server = Arc::new(EmbeddingServer::bind(VisionEncoder::new(small_config()), config).unwrap());
+    let addr = server.local_addr().unwrap();
+    let runner = {
+        let server = Arc::clone(&server);
+        thread::spawn(move || server.run())
+    };
+
+    let png = png_bytes();
+    let json_body = serde_json::json!({
+        "image": base64::engine::general_purpose::STANDARD.encode(&png)
+    })
+    .to_string();
+
+    // 四个并发请求应在同一批内完成
+    let barrier = Arc::new(Barrier::new(4));
+    let clients: Vec<_> = (0..4)
+        .map(|_| {
+            let (body, barrier) = (json_body.clone(), Arc::clone(&barrier));
+            thread::spawn(move || {
+                barrier.wait();
+                post(addr, "/embed", "application/json", body.as_bytes())
+            })
+        })
+        .collect();
+    for client in clients {
+        let (status, json) = client.join().unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(json["shape"], serde_json::json!([16, 8]));
+    }
+    assert_eq!(server.queue().batches_run(), 1);
+
+    let mut multipart = b"--XyZ\r\nContent-Disposition: form-data; name=\"image\"; filename=\"a.png\"\r\nContent-Type: image/png\r\n\r\n".to_vec();
+    multipart.extend_from_slice(&png);
+    multipart.extend_from_slice(b"\r\n--XyZ--\r\n");
+    let (status, json) = post(addr, "/preprocess", "multipart/form-data; boundary=XyZ", &multipart);
+    assert_eq!(status, 200);
+    assert_eq!(json["shape"], serde_json::json!([3, 32, 32]));
+
+    let (status, _) = post(addr, "/embed", "application/json", b"{\"image\": \"not base64!\"}");
+    assert_eq!(status, 400);
+
+    let (status, _) = post(addr, "/embed", "application/json", &vec![b' '; 8192]);
+    assert_eq!(status, 413);
+
+    server.shutdown();
+    runner.join().unwrap();
+}
+
+#[test]
+fn queue_survives_a_panicking_batch() {
+    let queue = BatchQueue::new(Arc::new(VisionEncoder::new(small_config())), 1, Duration::from_millis(1));
+    // 通道数不对, patch embed 的矩阵乘法 panic
+    assert!(queue.encode(Array3::zeros((2, 32, 32))).is_none());
+    let out = queue.encode(Array3::zeros((3, 32, 32))).unwrap();
+    assert_eq!(out.dim(), (16, 8));
+    assert_eq!(queue.batches_run(), 2);
+}
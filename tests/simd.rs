@@ -0,0 +1,62 @@
+// tests/simd.rs
+
+use cogvlm_image_preprocessor::simd::{self, SimdBackend};
+
+fn data(n: usize, seed: f32) -> Vec<f32> {
+    (0..n).map(|i| ((i as f32 + seed) * 0.61).sin() * 2.0).collect()
+}
+
+fn assert_close(a: &[f32], b: &[f32], backend: SimdBackend) {
+    for (x, y) in a.iter().zip(b) {
+        assert!((x - y).abs() < 1e-5, "{}: {} vs {}", backend.name(), x, y);
+    }
+}
+
+#[test]
+fn every_available_backend_matches_scalar() {
+    let backends = SimdBackend::available();
+    assert!(backends.contains(&SimdBackend::Scalar));
+    assert!(backends.contains(&simd::detect()));
+
+    // 覆盖 0、不足一个向量、整数倍和带尾部的长度
+    for &n in &[0usize, 2, 6, 16, 32, 38, 70] {
+        let (x, y) = (data(n, 0.0), data(n, 3.0));
+        let (cos, sin) = (data(n, 1.0), data(n, 2.0));
+
+        let mut copy_ref = vec![0.0; n];
+        simd::copy_with(SimdBackend::Scalar, &x, &mut copy_ref);
+        let mut glu_ref = vec![0.0; n];
+        simd::glu_with(SimdBackend::Scalar, &x, &y, &mut glu_ref);
+        let mut inter_ref = x.clone();
+        simd::rope_interleaved_with(SimdBackend::Scalar, &mut inter_ref, &cos, &sin);
+        let (mut lo_ref, mut hi_ref) = (x[..n / 2].to_vec(), y[..n / 2].to_vec());
+        simd::rope_half_with(SimdBackend::Scalar, &mut lo_ref, &mut hi_ref, &cos[..n / 2 * 2], &sin[..n / 2 * 2]);
+
+        for &backend in &backends {
+            let mut out = vec![0.0; n];
+            simd::copy_with(backend, &x, &mut out);
+            assert_eq!(out, copy_ref);
+
+            simd::glu_with(backend, &x, &y, &mut out);
+            assert_close(&out, &glu_ref, backend);
+
+            let mut row = x.clone();
+            simd::rope_interleaved_with(backend, &mut row, &cos, &sin);
+            assert_close(&row, &inter_ref, backend);
+
+            let (mut lo, mut hi) = (x[..n / 2].to_vec(), y[..n / 2].to_vec());
+            simd::rope_half_with(backend, &mut lo, &mut hi, &cos[..n / 2 * 2], &sin[..n / 2 * 2]);
+            assert_close(&lo, &lo_ref, backend);
+            assert_close(&hi, &hi_ref, backend);
+        }
+    }
+}
+
+#[test]
+fn backend_names_round_trip() {
+    for backend in SimdBackend::ALL {
+        assert_eq!(SimdBackend::from_name(backend.name()), Some(backend));
+    }
+    assert_eq!(SimdBackend::from_name(" AVX2 "), Some(SimdBackend::Avx2));
+    assert_eq!(SimdBackend::from_name("sse"), None);
+}
@@ -0,0 +1,115 @@
+// tests/stream.rs
+
+use cogvlm_image_preprocessor::augment::Augmentation;
+use cogvlm_image_preprocessor::processor::{process_images_in_batch, ImageProcessor};
+use cogvlm_image_preprocessor::stream::{ImageSource, StreamConfig};
+use image::{DynamicImage, ImageOutputFormat, RgbImage};
+use ndarray::{concatenate, Axis};
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn image(i: u8) -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(24 + i as u32, 20, |x, y| image::Rgb([x as u8 * 9, y as u8 * 11, i * 30])))
+}
+
+fn png_bytes(img: &DynamicImage) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    img.write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png).unwrap();
+    bytes
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cogvlm-stream-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn yields_fixed_size_batches_with_per_item_errors() {
+    let processor = ImageProcessor::new(16);
+    let dir = temp_dir("batches");
+    let path = dir.join("a.png");
+    image(0).save(&path).unwrap();
+
+    let sources = vec![
+        ImageSource::Path(path.clone()),
+        ImageSource::Bytes(png_bytes(&image(1))),
+        ImageSource::Bytes(b"not an image".to_vec()),
+        ImageSource::Path(dir.join("missing.png")),
+        ImageSource::Bytes(png_bytes(&image(4))),
+    ];
+    let config = StreamConfig { batch_size: 2, num_threads: Some(2), prefetch: 1 };
+    let batches: Vec<_> = processor.stream(sources, &config).unwrap().collect();
+
+    assert_eq!(batches.len(), 3);
+    assert_eq!(batches.iter().map(|b| b.indices.clone()).collect::<Vec<_>>(), vec![vec![0, 1], vec![], vec![4]]);
+    assert_eq!(batches[1].errors.iter().map(|e| e.0).collect::<Vec<_>>(), vec![2, 3]);
+    assert_eq!(batches[1].output.image.dim(), (0, 3, 16, 16));
+
+    assert_eq!(batches[0].output.metadata[0].source.as_deref(), Some(path.as_path()));
+    assert_eq!(batches[0].output.metadata[1].source, None);
+    assert_eq!(batches[2].output.image.index_axis(Axis(0), 0), processor.preprocess(&image(4)));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn file_urls_are_read_and_other_schemes_fail() {
+    let processor = ImageProcessor::new(16);
+    let dir = temp_dir("urls");
+    let path = dir.join("b.png");
+    image(2).save(&path).unwrap();
+
+    let sources = vec![
+        ImageSource::Url(format!("file://{}", path.display())),
+        ImageSource::Url("https://example.com/b.png".to_string()),
+    ];
+    let batch = processor.stream(sources, &StreamConfig::default()).unwrap().next().unwrap();
+    assert_eq!(batch.indices, vec![0]);
+    assert_eq!(batch.errors.len(), 1);
+    assert_eq!(batch.output.image.index_axis(Axis(0), 0), processor.preprocess(&image(2)));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn augmentation_does_not_depend_on_batch_size() {
+    let processor = ImageProcessor::new(16).with_augmentation(Augmentation::standard(3));
+    let images: Vec<_> = (0..5).map(image).collect();
+    let sources: Vec<_> = images.iter().map(png_bytes).collect();
+
+    let config = StreamConfig { batch_size: 2, num_threads: None, prefetch: 0 };
+    let outputs: Vec<_> = processor.stream(sources, &config).unwrap().map(|b| b.output.image).collect();
+    let views: Vec<_> = outputs.iter().map(|x| x.view()).collect();
+    let streamed = concatenate(Axis(0), &views).unwrap();
+    assert_eq!(streamed, process_images_in_batch(images, &processor).image);
+}
+
+#[test]
+fn decodes_a_bounded_number_of_batches_ahead() {
+    let processor = ImageProcessor::new(16);
+    let bytes = png_bytes(&image(0));
+    let pulled = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&pulled);
+    let sources = (0..20).map(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        bytes.clone()
+    });
+
+    let config = StreamConfig { batch_size: 2, num_threads: Some(1), prefetch: 1 };
+    let mut stream = processor.stream(sources, &config).unwrap();
+    assert_eq!(stream.next().unwrap().indices, vec![0, 1]);
+
+    // 调用方还没要下一批, 后台已开始读取: 排队的一批和正在处理的一批
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while pulled.load(Ordering::SeqCst) < 6 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+    }
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(pulled.load(Ordering::SeqCst), 6);
+
+    assert_eq!(stream.next().unwrap().indices, vec![2, 3]);
+    assert_eq!(stream.map(|b| b.indices.len()).sum::<usize>(), 16);
+}
@@ -0,0 +1,65 @@
+// tests/tiling.rs
+
+use cogvlm_image_preprocessor::encoder::{EncoderConfig, VisionEncoder};
+use cogvlm_image_preprocessor::processor::ImageProcessor;
+use cogvlm_image_preprocessor::tiling::{select_grid, TileConfig};
+use image::{DynamicImage, RgbImage};
+
+fn gradient_image(width: u32, height: u32) -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x * 7 % 256) as u8, (y * 5 % 256) as u8, ((x + y) * 3 % 256) as u8])
+    }))
+}
+
+#[test]
+fn grid_follows_aspect_ratio_and_image_size() {
+    assert_eq!(select_grid(1000, 500, 224, 6), (1, 2));
+    assert_eq!(select_grid(500, 1500, 224, 6), (3, 1));
+    // 比例同为 1:1 时, 小图保持单块, 大图用 2x2
+    assert_eq!(select_grid(100, 100, 224, 6), (1, 1));
+    assert_eq!(select_grid(1000, 1000, 224, 6), (2, 2));
+    assert_eq!(select_grid(4000, 100, 224, 6), (1, 6));
+}
+
+#[test]
+fn tiles_match_native_resolution_crops() {
+    // 原图恰好是 2x3 网格的大小时缩放为恒等, 每块应等于对应区域单独预处理的结果
+    let processor = ImageProcessor::new(32);
+    let img = gradient_image(96, 64);
+    let tiled = processor.preprocess_tiled(&img, &TileConfig::default());
+
+    assert_eq!(tiled.grid, (2, 3));
+    assert_eq!(tiled.tiles.len(), 6);
+    for tile in &tiled.tiles {
+        let crop = img.crop_imm(tile.col as u32 * 32, tile.row as u32 * 32, 32, 32);
+        assert_eq!(tile.image, processor.preprocess(&crop), "tile ({}, {})", tile.row, tile.col);
+    }
+    assert_eq!(tiled.thumbnail.unwrap(), processor.preprocess(&img));
+}
+
+#[test]
+fn forward_tiled_encodes_every_tile_and_thumbnail() {
+    let config = EncoderConfig {
+        image_size: 32,
+        patch_size: 8,
+        embed_dim: 16,
+        num_heads: 4,
+        ff_dim: 32,
+        num_layers: 1,
+        out_dim: 8,
+        seed: Some(0),
+        ..EncoderConfig::default()
+    };
+    let encoder = VisionEncoder::new(config);
+    let processor = ImageProcessor::new(32);
+    let tiled = processor.preprocess_tiled(&gradient_image(150, 70), &TileConfig { max_tiles: 4, thumbnail: true });
+
+    let out = encoder.forward_tiled(&tiled);
+    assert_eq!(out.grid, tiled.grid);
+    assert_eq!(out.tiles.len(), tiled.tiles.len());
+    for (emb, tile) in out.tiles.iter().zip(&tiled.tiles) {
+        assert_eq!((emb.row, emb.col), (tile.row, tile.col));
+        assert_eq!(emb.embedding, encoder.forward(&tile.image));
+    }
+    assert_eq!(out.thumbnail.unwrap().dim(), (16, 8));
+}
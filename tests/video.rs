@@ -0,0 +1,108 @@
+// tests/video.rs
+
+use cogvlm_image_preprocessor::encoder::{EncoderConfig, VisionEncoder};
+use cogvlm_image_preprocessor::processor::ImageProcessor;
+use cogvlm_image_preprocessor::video::{
+    apply_temporal, load_frames, sample_frames, temporal_position_embedding, FrameSampling, Temporal,
+};
+use image::codecs::gif::GifEncoder;
+use image::{DynamicImage, Frame, RgbImage, RgbaImage};
+use ndarray::Array2;
+use std::path::PathBuf;
+
+fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("cogvlm-video-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&path);
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn solid(value: u8) -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 30, image::Rgb([value, value / 2, 255 - value])))
+}
+
+#[test]
+fn uniform_sampling_takes_segment_centers() {
+    let frames: Vec<_> = (0..10).map(|i| solid(i * 10)).collect();
+    assert_eq!(sample_frames(&frames, 4, FrameSampling::Uniform), vec![1, 3, 6, 8]);
+    assert_eq!(sample_frames(&frames, 1, FrameSampling::Uniform), vec![5]);
+    assert_eq!(sample_frames(&frames[..3], 8, FrameSampling::Uniform), vec![0, 1, 2]);
+}
+
+#[test]
+fn scene_change_sampling_finds_cuts() {
+    // 三个镜头, 镜头内只有轻微变化
+    let frames: Vec<_> = (0..10u8).map(|i| solid(if i < 3 { 10 } else if i < 7 { 200 } else { 90 } + i)).collect();
+    assert_eq!(sample_frames(&frames, 3, FrameSampling::SceneChange), vec![0, 3, 7]);
+}
+
+#[test]
+fn load_frames_reads_gif_and_directories() {
+    let gif = temp_path("clip.gif");
+    {
+        let mut encoder = GifEncoder::new(std::fs::File::create(&gif).unwrap());
+        let frames = (0..3).map(|i| Frame::new(RgbaImage::from_pixel(8, 6, image::Rgba([i * 80, 0, 0, 255]))));
+        encoder.encode_frames(frames).unwrap();
+    }
+    let frames = load_frames(&gif).unwrap();
+    assert_eq!(frames.len(), 3);
+    assert_eq!((frames[2].width(), frames[2].height()), (8, 6));
+    std::fs::remove_file(&gif).unwrap();
+
+    let dir = temp_path("frames");
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, value) in [("b.png", 200), ("a.png", 10), ("notes.txt", 0)] {
+        if name.ends_with(".png") {
+            solid(value).save(dir.join(name)).unwrap();
+        } else {
+            std::fs::write(dir.join(name), "skip").unwrap();
+        }
+    }
+    let frames = load_frames(&dir).unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].to_rgb8().get_pixel(0, 0)[0], 10);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn temporal_pooling_and_position_embedding() {
+    let outputs: Vec<_> = (0..5).map(|t| Array2::from_elem((3, 4), t as f32)).collect();
+
+    let pooled = apply_temporal(outputs.clone(), Temporal::MeanPool { window: 2 });
+    assert_eq!(pooled.len(), 3);
+    assert_eq!(pooled[0], Array2::from_elem((3, 4), 0.5));
+    assert_eq!(pooled[2], Array2::from_elem((3, 4), 4.0));
+
+    let embedded = apply_temporal(outputs.clone(), Temporal::PositionEmbedding);
+    let table = temporal_position_embedding(5, 4);
+    for t in 0..5 {
+        assert_eq!(embedded[t].row(2), &outputs[t].row(2) + &table.row(t));
+    }
+    assert_eq!(apply_temporal(outputs.clone(), Temporal::None), outputs);
+}
+
+#[test]
+fn forward_video_encodes_sampled_frames() {
+    let config = EncoderConfig {
+        image_size: 32,
+        patch_size: 8,
+        embed_dim: 16,
+        num_heads: 2,
+        ff_dim: 32,
+        num_layers: 1,
+        out_dim: 8,
+        seed: Some(5),
+        ..EncoderConfig::default()
+    };
+    let encoder = VisionEncoder::new(config);
+    let frames: Vec<_> = (0..6).map(|i| solid(i * 40)).collect();
+    let clip = ImageProcessor::new(32).preprocess_video(&frames, 4, FrameSampling::Uniform);
+    assert_eq!(clip.frame_indices, vec![0, 2, 3, 5]);
+
+    let per_frame = encoder.forward_video(&clip.frames, Temporal::None);
+    assert_eq!(per_frame.len(), 4);
+    assert_eq!(per_frame[1], encoder.forward(&clip.frames[1]));
+    let pooled = encoder.forward_video(&clip.frames, Temporal::MeanPool { window: 4 });
+    assert_eq!(pooled.len(), 1);
+    assert_eq!(pooled[0].dim(), (16, 8));
+}